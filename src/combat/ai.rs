@@ -1,14 +1,17 @@
+use std::collections::VecDeque;
+
 use bevy::prelude::*;
 use bitflags::bitflags;
 use tinyvec::ArrayVec;
 
-use super::{weapon::*, *};
+use serde::Deserialize;
+
+use super::{monsterdefs::MonsterDefs, status::StatusEffects, *};
 use crate::{
-    combat::projectile::ProjectileType,
-    grid::{Coords, Grid},
+    grid::{Coords, DistanceField, Grid},
+    interactable::TriggerEvent,
     map::MapData,
     physics::Collider,
-    render::spritemap::SpriteSeq,
 };
 
 type RealF32 = ordered_float::NotNan<f32>;
@@ -18,161 +21,55 @@ fn rf32(f: f32) -> RealF32 {
 }
 
 const SIGHT_RADIUS: f32 = 16.0;
+const FLOW_FIELD_CACHE_SIZE: usize = 8;
+const FLEE_HP_FRACTION: f32 = 0.3;
+const DOOR_NOISE_COST: u32 = 4;
+const ALERT_DELAY: f32 = 0.6;
 
-impl MonsterType {
-    pub fn make_ai(&self) -> AI {
-        use MonsterType as MT;
-        match self {
-            MT::Imp => AI::new(Flags::Approach | Flags::Follow),
-            MT::Goblin => AI::new(Flags::Approach | Flags::Follow),
-            MT::EyeMonster1 => AI::new(Flags::Follow),
-            MT::Ettin => AI::new(Flags::Approach),
-            MT::Laima => AI::new(Flags::Approach),
-            MT::Snowman => AI::new(Flags::empty()),
-            MT::IronGolem => AI::new(Flags::empty()),
-            MT::EyeMonster2 => AI::new(Flags::empty()),
-            MT::Demon => AI::new(Flags::Approach | Flags::Follow),
-        }
-    }
-
-    pub fn jumps(&self) -> bool {
-        use MonsterType as MT;
-        matches!(
-            self,
-            MT::Imp | MT::Goblin | MT::Ettin | MT::IronGolem | MT::Demon
-        )
-    }
-
-    pub fn make_stats(&self) -> CreatureStats {
-        use MonsterType as MT;
-        let (speed, hp) = match self {
-            MT::Imp => (2.5, 5),
-            MT::Goblin => (3.0, 5),
-            MT::EyeMonster1 => (0.0, 10),
-            MT::EyeMonster2 => (2.0, 10),
-            MT::Ettin => (2.0, 20),
-            MT::Laima => (1.5, 18),
-            MT::Snowman => (0.8, 10),
-            MT::IronGolem => (1.0, 30),
-            MT::Demon => (1.0, 40),
-        };
-        CreatureStats {
-            speed,
-            hp,
-            hp_max: hp,
-            team: Team::Monsters,
-            monster_type: Some(*self),
-        }
-    }
-
-    pub fn make_weapon(&self) -> Weapon {
-        use MonsterType as MT;
-        match self {
-            MT::Imp => Weapon::new_melee(0.5, 3, DamageType::Fire),
-            MT::Goblin => Weapon::new_melee(0.5, 3, DamageType::Normal),
-            MT::EyeMonster1 => Weapon::new(
-                0.9,
-                10,
-                DamageType::Normal,
-                f32::INFINITY,
-                WeaponEffect::Ranged {
-                    ptype: ProjectileType::RedSpikes,
-                    accuracy: 0.1,
-                },
-            ),
-            MT::EyeMonster2 => Weapon::new_ranged(
-                0.6,
-                ProjectileType::RedSpikes,
-                f32::INFINITY,
-                10,
-                DamageType::Normal,
-            ),
-            MT::Ettin => Weapon::new(
-                0.9,
-                12,
-                DamageType::Normal,
-                9.0,
-                WeaponEffect::Ranged {
-                    ptype: ProjectileType::Rock,
-                    accuracy: 0.1,
-                },
-            ),
-            MT::Laima => {
-                Weapon::new_ranged(1.2, ProjectileType::Shock, 4.0, 20, DamageType::Electric)
-            }
-            MT::Snowman => Weapon::new(
-                0.15,
-                2,
-                DamageType::Cold,
-                7.0,
-                WeaponEffect::Ranged {
-                    ptype: ProjectileType::Snowball,
-                    accuracy: 0.3,
-                },
-            ),
-            MT::IronGolem => Weapon::new_ranged(
-                0.7,
-                ProjectileType::RedSpikes,
-                f32::INFINITY,
-                10,
-                DamageType::Normal,
-            ),
-            MT::Demon => Weapon::new(
-                0.9,
-                10,
-                DamageType::Fire,
-                f32::INFINITY,
-                WeaponEffect::RangedArc {
-                    ptype: ProjectileType::Fire,
-                    arc: 0.6,
-                    count: 5,
-                },
-            ),
-        }
-    }
+/// The AI settings of a monster archetype, as defined in `assets/data/monsters.ron`.
+#[derive(Deserialize)]
+pub struct AiDef {
+    flags: Flags,
+    #[serde(default)]
+    range: f32,
+    /// The range in which this monster warns other monsters after spotting the player. Silent monsters have `None`.
+    #[serde(default)]
+    alert_radius: Option<f32>,
+}
 
-    pub fn get_tile_seq(&self, tiles: &crate::render::spritemap::SpriteMap) -> SpriteSeq {
-        use MonsterType as MT;
-        let str = match self {
-            MT::Imp => "imp.png",
-            MT::Goblin => "goblin.png",
-            MT::EyeMonster1 => "eye_monster.png",
-            MT::EyeMonster2 => "eye_monster2.png",
-            MT::Ettin => "ettin.png",
-            MT::Laima => "laima.png",
-            MT::Snowman => "snowman.png",
-            MT::IronGolem => "iron_golem.png",
-            MT::Demon => "demon_fire.png",
-        };
-        tiles.get_monster(str)
-    }
-
-    pub fn get_score(&self) -> i32 {
-        match self {
-            MonsterType::Imp => 20,
-            MonsterType::Goblin => 20,
-            MonsterType::EyeMonster1 => 50,
-            MonsterType::EyeMonster2 => 70,
-            MonsterType::Ettin => 100,
-            MonsterType::Laima => 30,
-            MonsterType::Snowman => 60,
-            MonsterType::IronGolem => 120,
-            MonsterType::Demon => 200,
-        }
+impl AiDef {
+    pub fn make_ai(&self) -> AI {
+        AI::new(self.flags)
+            .with_range(self.range)
+            .with_alert(self.alert_radius)
     }
 }
 
+/// The player is usually the target, unless the monster holds a grudge against another creature.
 pub enum AIState {
     PlayerUnknown,
     SeePlayer(Vec3),
     SawPlayer(Coords),
 }
 bitflags! {
-    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+    #[serde(transparent)]
     pub struct Flags: u32 {
         const None = 0;
         const Approach = 0x1;
         const Follow = 0x2;
+        /// Tries to stay at `AI::range` from the player
+        const KeepDistance = 0x4;
+        /// Moves sideways while the player is in sight
+        const Strafe = 0x8;
+        /// Runs away when low on health
+        const Flee = 0x10;
+        /// Walks between room centres when the player is unknown
+        const Patrol = 0x20;
+        /// Humanoid monsters can open unlocked doors. Beasts are stopped by them.
+        const OpenDoors = 0x40;
+        /// Fights back against other monsters that hurt it
+        const Retaliates = 0x80;
     }
 }
 
@@ -180,6 +77,13 @@ bitflags! {
 pub struct AI {
     state: AIState,
     flags: Flags,
+    range: f32,
+    /// The planned route to a room centre, from where the patrol started up to and including the centre
+    patrol_path: Vec<Coords>,
+    alert_radius: Option<f32>,
+    alert_timer: Option<Timer>,
+    /// The creature this monster fights instead of the player, while it can see it
+    grudge: Option<Entity>,
 }
 
 impl AI {
@@ -187,12 +91,193 @@ impl AI {
         &self.state
     }
 
+    pub fn grudge(&self) -> Option<Entity> {
+        self.grudge
+    }
+
     fn new(flags: Flags) -> Self {
         Self {
             state: AIState::PlayerUnknown,
             flags,
+            range: 0.0,
+            patrol_path: vec![],
+            alert_radius: None,
+            alert_timer: None,
+            grudge: None,
         }
     }
+
+    /// Called when another monster hurt this one.
+    pub fn retaliate(&mut self, attacker: Entity) {
+        if self.flags.contains(Flags::Retaliates) {
+            self.grudge = Some(attacker);
+        }
+    }
+
+    fn with_range(mut self, range: f32) -> Self {
+        self.range = range;
+        self
+    }
+
+    fn with_alert(mut self, alert_radius: Option<f32>) -> Self {
+        self.alert_radius = alert_radius;
+        self
+    }
+
+    fn choose_step(
+        &mut self,
+        src: Coords,
+        stats: &CreatureStats,
+        map_data: &MapData,
+        flow_fields: &mut FlowFields,
+        rng: &mut fastrand::Rng,
+    ) -> Option<Coords> {
+        let opens_doors = self.flags.contains(Flags::OpenDoors);
+        let mut fuzzy_path = FuzzyPath::init(map_data, src, opens_doors);
+        let fleeing = self.flags.contains(Flags::Flee)
+            && (stats.hp as f32) < (stats.hp_max as f32) * FLEE_HP_FRACTION;
+
+        match self.state {
+            AIState::PlayerUnknown => {
+                if self.flags.contains(Flags::Patrol) {
+                    self.add_patrol(&mut fuzzy_path, map_data, rng);
+                } else {
+                    fuzzy_path.add_random(rf32(1.0), rng);
+                }
+            }
+            AIState::SeePlayer(player_pos) => {
+                let player_coords = Coords::from_vec(player_pos);
+                if fleeing {
+                    fuzzy_path.add_follow(
+                        rf32(-1.0),
+                        player_coords,
+                        rf32(0.0),
+                        map_data,
+                        flow_fields,
+                    );
+                } else if self
+                    .flags
+                    .intersects(Flags::Approach | Flags::KeepDistance | Flags::Strafe)
+                {
+                    if self.flags.contains(Flags::Approach) {
+                        fuzzy_path.add_follow(
+                            rf32(1.0),
+                            player_coords,
+                            rf32(f32::NEG_INFINITY),
+                            map_data,
+                            flow_fields,
+                        );
+                    }
+                    if self.flags.contains(Flags::KeepDistance) {
+                        // Positive when too far away, negative when too close
+                        let dist = (player_pos - src.to_vec(player_pos.y)).length();
+                        let weight = ((dist - self.range) / 2.0).clamp(-1.0, 1.0);
+
+                        fuzzy_path.add_follow(
+                            rf32(weight),
+                            player_coords,
+                            rf32(0.0),
+                            map_data,
+                            flow_fields,
+                        );
+                    }
+                    if self.flags.contains(Flags::Strafe) {
+                        fuzzy_path.add_strafe(rf32(0.5), player_coords);
+                        fuzzy_path.add_random(rf32(0.1), rng);
+                    }
+                } else {
+                    fuzzy_path.add_random(rf32(1.0), rng);
+                }
+            }
+            AIState::SawPlayer(last_seen_pos) => {
+                let (weight, on_end) = if fleeing { (-1.0, 0.0) } else { (1.0, 1.0) };
+                fuzzy_path.add_follow(
+                    rf32(weight),
+                    last_seen_pos,
+                    rf32(on_end),
+                    map_data,
+                    flow_fields,
+                );
+            }
+        };
+
+        fuzzy_path.choose()
+    }
+
+    /// Whether a monster at `pos` can stop looking for `last_seen`. Noises come from tiles that other creatures stand
+    /// on, so getting next to those is enough. Tiles that can't be reached are given up on.
+    fn done_searching(
+        &self,
+        pos: Coords,
+        last_seen: Coords,
+        map_data: &MapData,
+        flow_fields: &mut FlowFields,
+    ) -> bool {
+        if pos == last_seen {
+            return true;
+        }
+
+        let taken = map_data.monster_map[last_seen]
+            || last_seen == Coords::from_vec(map_data.player_pos.translation);
+        if taken && pos.eucledian_dist_sq(last_seen) <= 2 {
+            return true;
+        }
+
+        let opens_doors = self.flags.contains(Flags::OpenDoors);
+        !flow_fields
+            .get(last_seen, opens_doors, map_data)
+            .is_reachable(pos)
+    }
+
+    /// Walks along the route to a room centre. A new route is planned after arriving, or after being pushed off the
+    /// route, e.g. by other monsters in the way.
+    fn add_patrol(
+        &mut self,
+        fuzzy_path: &mut FuzzyPath,
+        map_data: &MapData,
+        rng: &mut fastrand::Rng,
+    ) {
+        let src = fuzzy_path.src;
+        let next = self
+            .patrol_path
+            .iter()
+            .position(|pos| *pos == src)
+            .and_then(|index| self.patrol_path.get(index + 1))
+            .copied()
+            .or_else(|| self.plan_patrol(src, fuzzy_path.opens_doors, map_data, rng));
+
+        match next {
+            Some(next) => fuzzy_path.add_approach(rf32(1.0), next, rf32(0.0)),
+            None => {
+                // Unreachable or already there. Choose another room next time.
+                self.patrol_path.clear();
+                fuzzy_path.add_random(rf32(1.0), rng);
+            }
+        }
+    }
+
+    /// Plans a route from `src` to the centre of a random room and returns its first step.
+    fn plan_patrol(
+        &mut self,
+        src: Coords,
+        opens_doors: bool,
+        map_data: &MapData,
+        rng: &mut fastrand::Rng,
+    ) -> Option<Coords> {
+        if map_data.room_centres.is_empty() {
+            return None;
+        }
+
+        let centre = map_data.room_centres[rng.usize(0..map_data.room_centres.len())];
+        let (path, _) = crate::grid::find_path8(
+            &map_data.solid_map,
+            walk_cost(opens_doors, map_data),
+            src,
+            centre,
+        )?;
+        self.patrol_path = path;
+        self.patrol_path.get(1).copied()
+    }
 }
 
 #[derive(Component)]
@@ -265,15 +350,62 @@ impl AiMover {
         self.f = f32::NEG_INFINITY; // Never trigger add_dist again
     }
 
+    /// Pushes the creature from the tile at `pos` into the neighbouring tile at `pos + step`, if that one is free.
+    pub fn knock_back(&mut self, pos: Coords, step: Coords, map_data: &mut MapData) -> bool {
+        debug_assert!(!self.is_removed());
+
+        // Leave the own tile first, so the creature can be pushed into the tile it was walking to
+        map_data.monster_map[self.to] = false;
+
+        let dest = pos + step;
+        let blocked = |c: Coords| map_data.solid_map[c] || map_data.monster_map[c];
+        let corner = step
+            .split()
+            .is_some_and(|(h, v)| blocked(pos + h) || blocked(pos + v));
+        if blocked(dest) || corner || dest == Coords::from_vec(map_data.player_pos.translation) {
+            map_data.monster_map[self.to] = true;
+            return false;
+        }
+
+        map_data.monster_map[dest] = true;
+        self.from = pos;
+        self.to = dest;
+        self.f = 0.0;
+        true
+    }
+
     pub fn add_dist(&mut self, dist: f32) -> bool {
         self.f += dist;
         self.f >= 1.0
     }
 }
 
-pub fn ai_los(map_data: Res<MapData>, mut monster_query: Query<(&mut AI, &Collider)>) {
+pub fn ai_los(
+    map_data: Res<MapData>,
+    mut monster_query: Query<(&mut AI, &Collider)>,
+    target_query: Query<&Collider, With<CreatureStats>>,
+) {
     for (mut ai, collider) in monster_query.iter_mut() {
+        if let Some(grudge) = ai.grudge {
+            match target_query.get(grudge) {
+                Ok(target) if map_data.line_of_sight(collider.pos, target.pos) => {
+                    ai.state = AIState::SeePlayer(target.pos);
+                    continue;
+                }
+                _ => {
+                    // The grudge is forgotten once the target is dead or out of sight
+                    ai.grudge = None;
+                    ai.state = AIState::PlayerUnknown;
+                }
+            }
+        }
+
         if map_data.can_see_player(collider.pos, SIGHT_RADIUS) {
+            let spotted = !matches!(ai.state, AIState::SeePlayer(_));
+            if spotted && ai.alert_radius.is_some() && ai.alert_timer.is_none() {
+                ai.alert_timer = Some(Timer::from_seconds(ALERT_DELAY, TimerMode::Once));
+            }
+
             ai.state = AIState::SeePlayer(map_data.player_pos.translation);
         } else if let AIState::SeePlayer(pos) = ai.state {
             if ai.flags.contains(Flags::Follow) {
@@ -285,15 +417,174 @@ pub fn ai_los(map_data: Res<MapData>, mut monster_query: Query<(&mut AI, &Collid
     }
 }
 
+/// Monsters that spotted the player warn the monsters around them after a short delay.
+/// Only monsters in the line of sight of the alerter are warned.
+pub fn ai_alert(
+    time: Res<Time>,
+    map_data: Res<MapData>,
+    mut monster_query: Query<(Entity, &mut AI, &Collider)>,
+) {
+    let mut alerts = vec![];
+    for (entity, mut ai, collider) in monster_query.iter_mut() {
+        let Some(timer) = &mut ai.alert_timer else {continue;};
+        if !timer.tick(time.delta()).finished() {
+            continue;
+        }
+        ai.alert_timer = None;
+
+        let target = match ai.state {
+            AIState::SeePlayer(pos) => Coords::from_vec(pos),
+            AIState::SawPlayer(pos) => pos,
+            AIState::PlayerUnknown => continue,
+        };
+        let radius = ai.alert_radius.unwrap_or(0.0);
+        alerts.push((entity, collider.pos, radius, target));
+    }
+
+    for (alerter, pos, radius, target) in alerts {
+        for (entity, mut ai, collider) in monster_query.iter_mut() {
+            if entity == alerter || matches!(ai.state, AIState::SeePlayer(_)) {
+                continue;
+            }
+
+            if pos.distance_squared(collider.pos) > radius * radius
+                || !map_data.line_of_sight(pos, collider.pos)
+            {
+                continue;
+            }
+
+            ai.state = AIState::SawPlayer(target);
+        }
+    }
+}
+
+/// Monsters that hear a noise will investigate its origin, unless they can already see the player.
+pub fn ai_hear(
+    map_data: Res<MapData>,
+    mut noise_events: EventReader<NoiseEvent>,
+    mut monster_query: Query<(&mut AI, &AiMover)>,
+) {
+    // Firing weapons and getting hurt happen at the same place, so only the loudest noise per tile is needed.
+    let mut noises = Vec::<(Coords, f32)>::new();
+    for noise in noise_events.read() {
+        let pos = Coords::from_vec(noise.pos);
+        match noises.iter_mut().find(|(p, _)| *p == pos) {
+            Some((_, loudness)) => *loudness = loudness.max(noise.loudness),
+            None => noises.push((pos, noise.loudness)),
+        }
+    }
+
+    for (origin, loudness) in noises {
+        let field = hear_field(&map_data, origin, loudness);
+
+        for (mut ai, mover) in monster_query.iter_mut() {
+            if mover.is_removed() || matches!(ai.state, AIState::SeePlayer(_)) {
+                continue;
+            }
+
+            if field.is_reachable(mover.to) {
+                ai.state = AIState::SawPlayer(origin);
+            }
+        }
+    }
+}
+
+/// Noise travels through open tiles. Closed doors are passable, but dampen the noise.
+fn hear_field(map_data: &MapData, origin: Coords, loudness: f32) -> DistanceField {
+    let max_dist = (loudness * crate::grid::STEP_ORTHOGONAL as f32) as u32;
+
+    crate::grid::distance_field8_within(
+        &map_data.tile_map,
+        |pos, tile| {
+            if tile.is_solid() {
+                None
+            } else if map_data.solid_map[pos] {
+                Some(DOOR_NOISE_COST)
+            } else {
+                Some(1)
+            }
+        },
+        &[origin],
+        max_dist,
+    )
+}
+
+/// The cost of walking onto a tile, for `find_path8` and the distance fields.
+fn walk_cost(opens_doors: bool, map_data: &MapData) -> impl Fn(Coords, bool) -> Option<u32> + '_ {
+    move |pos, solid| {
+        if !solid {
+            Some(1)
+        } else if opens_doors && map_data.is_unlocked_door(pos) {
+            Some(2) // Opening the door costs an extra step
+        } else {
+            None
+        }
+    }
+}
+
+/// Distance fields towards recent player positions, shared by all monsters.
+/// Monsters that lost track of the player usually look for a tile the player stood on a moment ago, so those fields are still cached.
+/// Monsters that open doors use separate fields, which lead through unlocked doors.
+#[derive(Resource, Default)]
+pub struct FlowFields {
+    fields: VecDeque<((Coords, bool), DistanceField)>,
+}
+
+impl FlowFields {
+    fn make_field(target: Coords, opens_doors: bool, map_data: &MapData) -> DistanceField {
+        crate::grid::distance_field8(
+            &map_data.solid_map,
+            walk_cost(opens_doors, map_data),
+            &[target],
+        )
+    }
+
+    pub fn get(&mut self, target: Coords, opens_doors: bool, map_data: &MapData) -> &DistanceField {
+        let key = (target, opens_doors);
+        let index = match self.fields.iter().position(|(k, _)| *k == key) {
+            Some(index) => index,
+            None => {
+                if self.fields.len() >= FLOW_FIELD_CACHE_SIZE {
+                    self.fields.pop_front();
+                }
+
+                self.fields
+                    .push_back((key, Self::make_field(target, opens_doors, map_data)));
+                self.fields.len() - 1
+            }
+        };
+        &self.fields[index].1
+    }
+
+    pub fn clear(&mut self) {
+        self.fields.clear();
+    }
+}
+
+pub fn update_flow_fields(map_data: Res<MapData>, mut flow_fields: ResMut<FlowFields>) {
+    // Only calculates the field when the player enters a new tile.
+    flow_fields.get(
+        Coords::from_vec(map_data.player_pos.translation),
+        false,
+        &map_data,
+    );
+}
+
+pub fn reset_flow_fields(mut flow_fields: ResMut<FlowFields>) {
+    flow_fields.clear();
+}
+
 struct FuzzyPath {
     dirs: ArrayVec<[(Coords, RealF32); 8]>,
     src: Coords,
+    opens_doors: bool,
 }
 
 impl FuzzyPath {
-    fn init(map_data: &MapData, src: Coords) -> Self {
+    fn init(map_data: &MapData, src: Coords, opens_doors: bool) -> Self {
         let zero: RealF32 = RealF32::new(0.0).unwrap();
         let mut dirs = ArrayVec::<[(Coords, RealF32); 8]>::new();
+        let player_tile = Coords::from_vec(map_data.player_pos.translation);
 
         // Find all directions the creature can move towards
         for dz in -1..=1 {
@@ -304,9 +595,16 @@ impl FuzzyPath {
                 }
 
                 let dest = src + dir;
-                if map_data.solid_map[dest] || map_data.monster_map[dest] {
+                let door = opens_doors && map_data.is_unlocked_door(dest);
+                if (map_data.solid_map[dest] && !door) || map_data.monster_map[dest] {
                     continue; // Tile is blocked
                 }
+                if dest == player_tile {
+                    continue; // Don't walk into the player
+                }
+                if map_data.tile_map[dest].is_lava() && !map_data.tile_map[src].is_lava() {
+                    continue; // Don't walk into lava
+                }
 
                 if let Some((h, v)) = dir.split() {
                     if map_data.solid_map[src + h] || map_data.solid_map[src + v] {
@@ -318,7 +616,11 @@ impl FuzzyPath {
                 dirs.push((dir, zero));
             }
         }
-        Self { dirs, src }
+        Self {
+            dirs,
+            src,
+            opens_doors,
+        }
     }
 
     fn choose(&self) -> Option<Coords> {
@@ -328,79 +630,417 @@ impl FuzzyPath {
             .map(|(dir, _)| self.src + *dir)
     }
 
-    fn add_random(&mut self, weight: RealF32) {
+    fn add_random(&mut self, weight: RealF32, rng: &mut fastrand::Rng) {
         for (_, val) in &mut self.dirs {
-            *val += weight * rf32(fastrand::f32());
+            *val += weight * rf32(rng.f32());
         }
     }
 
+    /// `on_end` is used as is when `to` is the current tile. It isn't multiplied with the weight.
     fn add_approach(&mut self, weight: RealF32, to: Coords, on_end: RealF32) {
         let delta_to = to - self.src;
 
         for (dir, val) in &mut self.dirs {
             let dot = dir.dot_norm(delta_to);
-            let dot = RealF32::new(dot).unwrap_or(on_end);
 
-            *val += weight * dot;
+            *val += match RealF32::new(dot) {
+                Ok(dot) => weight * dot,
+                Err(_) => on_end,
+            };
+        }
+    }
+
+    /// Prefers moving perpendicular to the direction of `around`.
+    fn add_strafe(&mut self, weight: RealF32, around: Coords) {
+        let delta_to = around - self.src;
+
+        for (dir, val) in &mut self.dirs {
+            let Ok(dot) = RealF32::new(dir.dot_norm(delta_to)) else {continue;};
+
+            *val += weight * (rf32(1.0) - dot.abs());
+        }
+    }
+
+    /// Moves downhill in the field for positive weights and uphill for negative weights.
+    fn add_flow(&mut self, weight: RealF32, field: &DistanceField) {
+        let src_dist = field.get(self.src) as f32;
+
+        for (dir, val) in &mut self.dirs {
+            let dest_dist = field.get(self.src + *dir);
+            if dest_dist == u32::MAX {
+                continue;
+            }
+
+            let gain = (src_dist - dest_dist as f32) / crate::grid::STEP_ORTHOGONAL as f32;
+            *val += weight * rf32(gain);
+        }
+    }
+
+    /// Walks along the flow field towards `to`. Falls back to a straight approach if `to` can't be reached from here.
+    fn add_follow(
+        &mut self,
+        weight: RealF32,
+        to: Coords,
+        on_end: RealF32,
+        map_data: &MapData,
+        flow_fields: &mut FlowFields,
+    ) {
+        let field = flow_fields.get(to, self.opens_doors, map_data);
+        if field.is_reachable(self.src) {
+            self.add_flow(weight, field);
+        } else {
+            self.add_approach(weight, to, on_end);
         }
     }
 }
 
 pub fn ai_move(
     mut map_data: ResMut<MapData>,
+    mut flow_fields: ResMut<FlowFields>,
+    monster_defs: Res<MonsterDefs>,
     time: Res<Time>,
-    mut monster_query: Query<(&mut AI, &mut AiMover, &CreatureStats, &mut Collider)>,
+    mut rng: ResMut<CombatRng>,
+    mut trigger_events: EventWriter<TriggerEvent>,
+    mut monster_query: Query<(
+        Entity,
+        &mut AI,
+        &mut AiMover,
+        &CreatureStats,
+        &StatusEffects,
+        &mut Collider,
+    )>,
 ) {
     let time = time.delta().as_secs_f32();
-    for (mut ai_state, mut ai_mover, stats, mut collider) in monster_query.iter_mut() {
-        if stats.speed == 0.0 {
+    for (entity, mut ai_state, mut ai_mover, stats, effects, mut collider) in
+        monster_query.iter_mut()
+    {
+        if stats.speed == 0.0 || effects.is_stunned() {
             continue;
         }
 
-        if ai_mover.add_dist(stats.speed * time) {
+        if ai_mover.add_dist(stats.speed * effects.speed_factor() * time) {
             let ai_pos = ai_mover.to;
-            let mut fuzzy_path = FuzzyPath::init(&map_data, ai_pos);
-
-            match ai_state.state {
-                AIState::PlayerUnknown => {
-                    fuzzy_path.add_random(rf32(1.0));
-                }
-                AIState::SeePlayer(player_pos) => {
-                    if ai_state.flags.contains(Flags::Approach) {
-                        fuzzy_path.add_approach(
-                            rf32(1.0),
-                            Coords::from_vec(player_pos),
-                            rf32(f32::NEG_INFINITY),
-                        );
-                    } else {
-                        fuzzy_path.add_random(rf32(1.0));
-                    }
-                }
-                AIState::SawPlayer(last_seen_pos) => {
-                    fuzzy_path.add_approach(rf32(1.0), last_seen_pos, rf32(1.0));
-                }
-            };
+            let dest_pos =
+                ai_state.choose_step(ai_pos, stats, &map_data, &mut flow_fields, &mut rng.0);
 
-            let dest_pos = fuzzy_path.choose();
-
-            if let Some(dest_pos) = dest_pos {
+            let closed_door = dest_pos
+                .filter(|pos| map_data.solid_map[*pos])
+                .and_then(|pos| map_data.doors[pos]);
+            if let Some(door) = closed_door {
+                // Open the door first and walk through it next step
+                trigger_events.send(TriggerEvent {
+                    target: door.entity,
+                    instigator: Some(entity),
+                });
+                ai_mover.f = 1.0;
+            } else if let Some(dest_pos) = dest_pos {
                 ai_mover.set_next_square(dest_pos, &mut map_data.monster_map);
             } else {
                 ai_mover.f = 1.0;
             }
 
             // Cleanup
-            if let AIState::SawPlayer(pos) = ai_state.state {
-                if Some(pos) == dest_pos {
+            if let AIState::SawPlayer(last_seen) = ai_state.state {
+                let pos = ai_mover.to;
+                if ai_state.done_searching(pos, last_seen, &map_data, &mut flow_fields) {
                     ai_state.state = AIState::PlayerUnknown;
                 }
             };
         }
 
         let ai_jumps = match stats.monster_type {
-            Some(t) => t.jumps(),
+            Some(t) => monster_defs.get(t).jumps,
             None => false,
         };
         collider.pos = ai_mover.to_vec(ai_jumps, stats.speed);
     }
 }
+
+/// Turns the direction of a hit into a step to one of the 8 neighbouring tiles.
+fn knockback_step(dir: Vec3) -> Option<Coords> {
+    // Diagonal steps for directions within 22.5 degrees of the diagonal
+    const DIAGONAL: f32 = 0.38;
+    let dir = Vec3::new(dir.x, 0.0, dir.z).normalize_or_zero();
+    let step = |f: f32| {
+        if f > DIAGONAL {
+            1
+        } else if f < -DIAGONAL {
+            -1
+        } else {
+            0
+        }
+    };
+
+    let step = Coords::new(step(dir.x), step(dir.z));
+    (step != Coords::ZERO).then_some(step)
+}
+
+/// Heavy hits push monsters into the next tile. Immobile monsters can't be pushed.
+pub fn apply_knockback(
+    mut ev_knockback: EventReader<KnockbackEvent>,
+    mut map_data: ResMut<MapData>,
+    mut monster_query: Query<(&mut AiMover, &CreatureStats, &Collider)>,
+) {
+    for ev in ev_knockback.read() {
+        let Ok((mut mover, stats, collider)) = monster_query.get_mut(ev.target) else {continue;};
+        if stats.speed == 0.0 || mover.is_removed() {
+            continue;
+        }
+        let Some(step) = knockback_step(ev.dir) else {continue;};
+
+        mover.knock_back(Coords::from_vec(collider.pos), step, &mut map_data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{CeilingTile, DoorRef, FloorTile, Tile, WallTile};
+
+    /// Walls are '#', closed doors are 'D', lava is 'L' and everything else is floor.
+    fn make_map_data(rows: &[&str]) -> MapData {
+        let mut tile_map = Grid::new(rows[0].len() as i32, rows.len() as i32);
+        let mut solid_map = Grid::new_from(rows[0].len() as i32, rows.len() as i32, true);
+        let mut doors = Grid::new(rows[0].len() as i32, rows.len() as i32);
+        for (z, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let pos = Coords::new(x as i32, z as i32);
+                tile_map[pos] = match c {
+                    '#' => Tile::Wall(WallTile::Castle),
+                    'L' => Tile::Open(FloorTile::Lava, CeilingTile::White),
+                    _ => Tile::Open(FloorTile::Sand, CeilingTile::White),
+                };
+                solid_map[pos] = c == '#' || c == 'D';
+                if c == 'D' {
+                    doors[pos] = Some(DoorRef {
+                        entity: Entity::from_raw(0),
+                        required_key: 0,
+                    });
+                }
+            }
+        }
+
+        MapData {
+            los_map: solid_map.clone(),
+            monster_map: solid_map.map(|_| false),
+            solid_map,
+            tile_map,
+            doors,
+            ..Default::default()
+        }
+    }
+
+    const OPEN_ROOM: [&str; 9] = [
+        "#########",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#########",
+    ];
+
+    fn step(ai: &mut AI, src: Coords, hp: i16, map_data: &MapData) -> Coords {
+        let mut stats = CreatureStats::player();
+        stats.hp = hp;
+        stats.hp_max = 10;
+
+        let mut rng = fastrand::Rng::with_seed(0);
+        ai.choose_step(src, &stats, map_data, &mut FlowFields::default(), &mut rng)
+            .expect("The monster should be able to move")
+    }
+
+    fn dist_sq(p0: Coords, p1: Coords) -> i32 {
+        p0.eucledian_dist_sq(p1)
+    }
+
+    #[test]
+    fn follow_around_wall() {
+        let map_data = make_map_data(&[
+            "#########",
+            "#.......#",
+            "#.#####.#",
+            "#.#...#.#",
+            "#.#...#.#",
+            "#.......#",
+            "#########",
+        ]);
+        let mut ai = AI::new(Flags::Follow);
+        ai.state = AIState::SawPlayer(Coords::new(4, 1));
+
+        // The player is north, but the only way out of the room is south
+        let dest = step(&mut ai, Coords::new(4, 3), 10, &map_data);
+        assert_eq!(dest.z, 4);
+    }
+
+    #[test]
+    fn avoid_lava() {
+        let map_data = make_map_data(&["#####", "#...#", "#LLL#", "#...#", "#####"]);
+        for _ in 0..20 {
+            let mut ai = AI::new(Flags::Approach);
+            ai.state = AIState::SeePlayer(Coords::new(2, 3).to_vec(0.5));
+
+            let dest = step(&mut ai, Coords::new(2, 1), 10, &map_data);
+            assert_eq!(dest.z, 1);
+        }
+    }
+
+    #[test]
+    fn retaliate() {
+        let attacker = Entity::from_raw(1);
+        let mut ai = AI::new(Flags::Approach);
+        ai.retaliate(attacker);
+        assert_eq!(ai.grudge, None);
+
+        let mut ai = AI::new(Flags::Approach | Flags::Retaliates);
+        ai.retaliate(attacker);
+        assert_eq!(ai.grudge, Some(attacker));
+    }
+
+    #[test]
+    fn knockback() {
+        let step = |x, z| Some(Coords::new(x, z));
+        assert_eq!(knockback_step(Vec3::new(1.0, 0.3, 0.1)), step(1, 0));
+        assert_eq!(knockback_step(Vec3::new(-1.0, 0.0, 0.9)), step(-1, 1));
+        assert_eq!(knockback_step(Vec3::Y), None);
+
+        let mut map_data = make_map_data(&["#####", "#...#", "#.#.#", "#...#", "#####"]);
+        map_data.monster_map[(3, 1)] = true;
+        map_data.player_pos = Transform::from_translation(Coords::new(1, 3).to_vec(0.5));
+        let mut mover = AiMover::new(Coords::new(2, 1), &mut map_data.monster_map);
+        let mut push = |x: i32, z: i32| {
+            let pos = mover.to;
+            mover.knock_back(pos, Coords::new(x, z), &mut map_data)
+        };
+
+        assert!(!push(0, 1)); // Wall
+        assert!(!push(1, 1)); // Corner
+        assert!(!push(1, 0)); // Monster
+        assert!(push(-1, 0));
+        assert!(push(0, 1));
+        assert!(!push(0, 1)); // Player
+
+        assert_eq!(mover.to, Coords::new(1, 2));
+        assert!(!map_data.monster_map[(2, 1)] && !map_data.monster_map[(1, 1)]);
+        assert!(map_data.monster_map[(1, 2)]);
+    }
+
+    #[test]
+    fn keep_distance() {
+        let map_data = make_map_data(&OPEN_ROOM);
+        let player = Coords::new(1, 4);
+        let mut ai = AI::new(Flags::KeepDistance).with_range(4.0);
+        ai.state = AIState::SeePlayer(player.to_vec(0.7));
+
+        let src = Coords::new(2, 4);
+        let dest = step(&mut ai, src, 10, &map_data);
+        assert!(dist_sq(dest, player) > dist_sq(src, player));
+
+        let src = Coords::new(7, 4);
+        let dest = step(&mut ai, src, 10, &map_data);
+        assert!(dist_sq(dest, player) < dist_sq(src, player));
+    }
+
+    #[test]
+    fn strafe() {
+        let map_data = make_map_data(&OPEN_ROOM);
+        let player = Coords::new(1, 4);
+        let mut ai = AI::new(Flags::Strafe);
+        ai.state = AIState::SeePlayer(player.to_vec(0.7));
+
+        let dest = step(&mut ai, Coords::new(5, 4), 10, &map_data);
+        assert_eq!(dest.x, 5);
+    }
+
+    #[test]
+    fn flee_when_hurt() {
+        let map_data = make_map_data(&OPEN_ROOM);
+        let player = Coords::new(4, 4);
+        let src = Coords::new(5, 5);
+        let mut ai = AI::new(Flags::Approach | Flags::Flee);
+        ai.state = AIState::SeePlayer(player.to_vec(0.7));
+
+        let dest = step(&mut ai, src, 10, &map_data);
+        assert!(dist_sq(dest, player) < dist_sq(src, player));
+
+        let dest = step(&mut ai, src, 2, &map_data);
+        assert!(dist_sq(dest, player) > dist_sq(src, player));
+    }
+
+    #[test]
+    fn hear_through_doors() {
+        let map_data = make_map_data(&["#########", "#...D...#", "#########"]);
+        let origin = Coords::new(1, 1);
+
+        assert!(hear_field(&map_data, origin, 3.0).is_reachable(Coords::new(3, 1)));
+        assert!(!hear_field(&map_data, origin, 6.0).is_reachable(Coords::new(5, 1)));
+        assert!(hear_field(&map_data, origin, 7.0).is_reachable(Coords::new(5, 1)));
+    }
+
+    #[test]
+    fn stop_next_to_noise() {
+        let mut map_data = make_map_data(&OPEN_ROOM);
+        let mut flow_fields = FlowFields::default();
+        let ai = AI::new(Flags::Follow);
+        let shooter = Coords::new(4, 4);
+        map_data.monster_map[shooter] = true;
+
+        assert!(!ai.done_searching(Coords::new(2, 4), shooter, &map_data, &mut flow_fields));
+        assert!(ai.done_searching(Coords::new(3, 3), shooter, &map_data, &mut flow_fields));
+
+        // Free tiles have to be reached
+        map_data.monster_map[shooter] = false;
+        assert!(!ai.done_searching(Coords::new(3, 3), shooter, &map_data, &mut flow_fields));
+        assert!(ai.done_searching(shooter, shooter, &map_data, &mut flow_fields));
+
+        let map_data = make_map_data(&["#######", "#..#..#", "#######"]);
+        let walled_off = Coords::new(5, 1);
+        assert!(ai.done_searching(Coords::new(1, 1), walled_off, &map_data, &mut flow_fields));
+    }
+
+    #[test]
+    fn patrol() {
+        let mut map_data = make_map_data(&OPEN_ROOM);
+        let centre = Coords::new(6, 2);
+        map_data.room_centres = vec![centre];
+        let mut ai = AI::new(Flags::Patrol);
+
+        let src = Coords::new(2, 6);
+        let dest = step(&mut ai, src, 10, &map_data);
+        assert_eq!(dest, Coords::new(3, 5));
+        assert_eq!(ai.patrol_path.first(), Some(&src));
+        assert_eq!(ai.patrol_path.last(), Some(&centre));
+
+        // Keeps following the route
+        let dest = step(&mut ai, dest, 10, &map_data);
+        assert_eq!(dest, Coords::new(4, 4));
+
+        // Choose a new room after arriving
+        step(&mut ai, centre, 10, &map_data);
+        assert!(ai.patrol_path.is_empty());
+    }
+
+    #[test]
+    fn open_doors() {
+        let map_data = make_map_data(&["#######", "#..D..#", "#######"]);
+        let src = Coords::new(2, 1);
+        let door = Coords::new(3, 1);
+
+        let choose = |flags: Flags| {
+            let mut ai = AI::new(flags);
+            ai.state = AIState::SawPlayer(Coords::new(5, 1));
+            ai.choose_step(
+                src,
+                &CreatureStats::player(),
+                &map_data,
+                &mut FlowFields::default(),
+                &mut fastrand::Rng::with_seed(0),
+            )
+        };
+
+        assert_eq!(choose(Flags::Follow | Flags::OpenDoors), Some(door));
+        assert_ne!(choose(Flags::Follow), Some(door));
+    }
+}
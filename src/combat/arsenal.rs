@@ -0,0 +1,177 @@
+use bevy::prelude::*;
+
+use super::{
+    player::{InputAction, Player},
+    weapon::{AmmoType, Weapon, WeaponType},
+    weapondefs::WeaponDefs,
+};
+
+/// The weapons the player carries. The selected weapon is moved into the player's `Weapon` component, so its slot is empty.
+#[derive(Component)]
+pub struct Arsenal {
+    loadout: Vec<WeaponType>,
+    slots: Vec<Option<Weapon>>,
+    current: usize,
+    ammo: [i16; AmmoType::ALL.len()],
+}
+
+impl Arsenal {
+    /// Returns the arsenal and the initially selected weapon.
+    pub fn new(weapon_defs: &WeaponDefs) -> (Self, Weapon) {
+        let loadout = weapon_defs.loadout().to_vec();
+        let mut slots: Vec<_> = loadout
+            .iter()
+            .map(|w| {
+                let mut weapon = weapon_defs.make_weapon(*w);
+                weapon.set_fire_state(false); // Unlike AI's don't automatically fire.
+                Some(weapon)
+            })
+            .collect();
+        let weapon = slots[0].take().unwrap();
+
+        let arsenal = Self {
+            loadout,
+            slots,
+            current: 0,
+            ammo: AmmoType::ALL.map(|t| t.pickup_amount()),
+        };
+        (arsenal, weapon)
+    }
+
+    pub fn current(&self) -> WeaponType {
+        self.loadout[self.current]
+    }
+
+    /// The ammo left for the selected weapon, or `None` if it doesn't need any.
+    pub fn current_ammo(&self, weapon_defs: &WeaponDefs) -> Option<i16> {
+        let ammo_type = weapon_defs.get(self.current()).ammo?;
+        Some(self.ammo(ammo_type))
+    }
+
+    pub fn ammo(&self, ammo_type: AmmoType) -> i16 {
+        self.ammo[ammo_type as usize]
+    }
+
+    /// Returns false if the ammo pool is already full.
+    pub fn add_ammo(&mut self, ammo_type: AmmoType, amount: i16) -> bool {
+        let ammo = &mut self.ammo[ammo_type as usize];
+        if *ammo >= ammo_type.max() {
+            return false;
+        }
+
+        *ammo = i16::min(*ammo + amount, ammo_type.max());
+        true
+    }
+
+    pub fn has_ammo(&self, weapon: &Weapon) -> bool {
+        weapon.ammo_type.is_none_or(|t| self.ammo(t) > 0)
+    }
+
+    pub fn use_ammo(&mut self, weapon: &Weapon) {
+        if let Some(ammo_type) = weapon.ammo_type {
+            self.ammo[ammo_type as usize] -= 1;
+        }
+    }
+
+    /// Swaps the weapon in the player's hands with the one in the given slot.
+    pub fn select(&mut self, slot: usize, weapon: &mut Weapon) -> bool {
+        if slot == self.current || slot >= self.slots.len() {
+            return false;
+        }
+        let Some(mut new_weapon) = self.slots[slot].take() else {return false;};
+
+        new_weapon.set_fire_state(false);
+        self.slots[self.current] = Some(std::mem::replace(weapon, new_weapon));
+        self.current = slot;
+        true
+    }
+
+    /// Selects the next (or previous) weapon that still has ammo.
+    pub fn cycle(&mut self, forward: bool, weapon: &mut Weapon) -> bool {
+        let count = self.slots.len();
+        for i in 1..count {
+            let slot = if forward {
+                (self.current + i) % count
+            } else {
+                (self.current + count - i) % count
+            };
+
+            if self.slots[slot].as_ref().is_some_and(|w| self.has_ammo(w)) {
+                return self.select(slot, weapon);
+            }
+        }
+        false
+    }
+}
+
+pub fn switch_weapons(
+    mut acts: EventReader<InputAction>,
+    mut player_query: Query<(&mut Weapon, &mut Arsenal), With<Player>>,
+) {
+    if let Ok((mut weapon, mut arsenal)) = player_query.get_single_mut() {
+        for act in acts.read() {
+            match act {
+                InputAction::NextWeapon => arsenal.cycle(true, &mut weapon),
+                InputAction::PrevWeapon => arsenal.cycle(false, &mut weapon),
+                InputAction::SelectWeapon(slot) => arsenal.select(*slot as usize, &mut weapon),
+                _ => false,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combat::weapondefs::tests::weapon_defs;
+
+    #[test]
+    fn select_swaps_weapons() {
+        let defs = weapon_defs();
+        let (mut arsenal, mut weapon) = Arsenal::new(&defs);
+        assert_eq!(arsenal.current(), defs.find("wand").unwrap());
+
+        assert!(arsenal.select(2, &mut weapon));
+        assert_eq!(arsenal.current(), defs.find("fire_staff").unwrap());
+        assert_eq!(
+            arsenal.current_ammo(&defs),
+            Some(arsenal.ammo(AmmoType::Fire))
+        );
+        assert_eq!(weapon.ammo_type, Some(AmmoType::Fire));
+
+        assert!(!arsenal.select(2, &mut weapon));
+        assert!(arsenal.select(0, &mut weapon));
+        assert_eq!(weapon.ammo_type, None);
+    }
+
+    #[test]
+    fn cycle_skips_empty_weapons() {
+        let defs = weapon_defs();
+        let (mut arsenal, mut weapon) = Arsenal::new(&defs);
+        arsenal.ammo[AmmoType::Fire as usize] = 0;
+
+        assert!(arsenal.cycle(true, &mut weapon));
+        assert_eq!(arsenal.current(), defs.find("dagger").unwrap());
+        assert!(arsenal.cycle(true, &mut weapon));
+        assert_eq!(arsenal.current(), defs.find("shock_wand").unwrap());
+        assert!(arsenal.cycle(false, &mut weapon));
+        assert_eq!(arsenal.current(), defs.find("dagger").unwrap());
+    }
+
+    #[test]
+    fn ammo() {
+        let (mut arsenal, mut weapon) = Arsenal::new(&weapon_defs());
+        arsenal.select(3, &mut weapon);
+
+        let start = arsenal.ammo(AmmoType::Shock);
+        arsenal.use_ammo(&weapon);
+        assert_eq!(arsenal.ammo(AmmoType::Shock), start - 1);
+
+        assert!(arsenal.add_ammo(AmmoType::Shock, 1000));
+        assert_eq!(arsenal.ammo(AmmoType::Shock), AmmoType::Shock.max());
+        assert!(!arsenal.add_ammo(AmmoType::Shock, 1));
+
+        arsenal.ammo[AmmoType::Shock as usize] = 0;
+        assert!(!arsenal.has_ammo(&weapon));
+    }
+}
@@ -0,0 +1,255 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{
+    ai::{AIState, AI},
+    monsterdefs::MonsterDefs,
+    weapon::Weapon,
+    weapondefs::WeaponDefs,
+    CombatRng, CreatureStats,
+};
+use crate::{
+    grid::Coords,
+    physics::Collider,
+    spawner::Spawner,
+    ui::menus::{MenuInfo, MenuType},
+};
+
+/// Bosses stop summoning while this many minions are alive.
+const MAX_MINIONS: usize = 8;
+/// Minions appear at most this many tiles away from the boss.
+const SUMMON_RADIUS: i32 = 3;
+
+/// A stage of a boss fight, as defined in `assets/data/monsters.ron`.
+#[derive(Deserialize)]
+pub struct BossPhase {
+    /// The phase starts when the HP of the boss drops to this fraction of its max HP
+    pub hp: f32,
+    pub weapon: String,
+    #[serde(default)]
+    pub summons: Vec<String>,
+    #[serde(default)]
+    pub summon_interval: f32,
+}
+
+#[derive(Deserialize)]
+pub struct BossDef {
+    pub phases: Vec<BossPhase>,
+}
+
+impl BossDef {
+    /// The last phase whose HP threshold has been reached.
+    pub fn phase_at(&self, hp: i16, hp_max: i16) -> usize {
+        self.phases
+            .iter()
+            .rposition(|phase| hp as f32 <= phase.hp * hp_max as f32)
+            .unwrap_or(0)
+    }
+
+    /// Phases should start at full health and have decreasing thresholds.
+    pub fn is_valid(&self) -> bool {
+        let Some(first) = self.phases.first() else {return false;};
+        first.hp >= 1.0 && self.phases.windows(2).all(|w| w[0].hp > w[1].hp)
+    }
+}
+
+#[derive(Component)]
+pub struct Boss {
+    phase: usize,
+    summon_timer: Timer,
+    /// Bosses can't be hurt while their phylactery exists.
+    pub shielded: bool,
+}
+
+impl Boss {
+    pub fn new(def: &BossDef) -> Self {
+        Self {
+            phase: 0,
+            summon_timer: Timer::from_seconds(def.phases[0].summon_interval, TimerMode::Once),
+            shielded: true,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Phylactery;
+
+/// A monster summoned by a boss.
+#[derive(Component)]
+pub struct Minion;
+
+pub fn update_boss_phases(
+    monster_defs: Res<MonsterDefs>,
+    weapon_defs: Res<WeaponDefs>,
+    mut boss_query: Query<(&mut Boss, &CreatureStats, &mut Weapon)>,
+) {
+    for (mut boss, stats, mut weapon) in boss_query.iter_mut() {
+        let Some(monster_type) = stats.monster_type else {continue;};
+        let Some(def) = &monster_defs.get(monster_type).boss else {continue;};
+
+        let phase = def.phase_at(stats.hp, stats.hp_max);
+        if phase == boss.phase {
+            continue;
+        }
+
+        let phase_def = &def.phases[phase];
+        let weapon_type = weapon_defs
+            .find(&phase_def.weapon)
+            .expect("Weapons are checked when loading the monsters");
+        *weapon = weapon_defs.make_weapon(weapon_type);
+
+        // Summon the minions of the new phase right away
+        boss.phase = phase;
+        let interval = std::time::Duration::from_secs_f32(phase_def.summon_interval);
+        boss.summon_timer = Timer::new(interval, TimerMode::Once);
+        boss.summon_timer.tick(interval);
+    }
+}
+
+pub fn update_boss_shield(
+    mut boss_query: Query<&mut Boss>,
+    phylactery_query: Query<(), With<Phylactery>>,
+) {
+    let shielded = !phylactery_query.is_empty();
+    for mut boss in boss_query.iter_mut() {
+        boss.shielded = shielded;
+    }
+}
+
+#[allow(clippy::too_many_arguments)] // Not really applicable for bevy systems
+pub fn summon_minions(
+    time: Res<Time>,
+    mut rng: ResMut<CombatRng>,
+    mut boss_query: Query<(&mut Boss, &AI, &CreatureStats, &Collider)>,
+    minion_query: Query<(), With<Minion>>,
+    commands: Commands,
+    map_data: ResMut<crate::map::MapData>,
+    meshes: ResMut<Assets<Mesh>>,
+    render_res: ResMut<crate::render::RenderResource>,
+    monster_defs: Res<MonsterDefs>,
+    weapon_defs: Res<WeaponDefs>,
+) {
+    let mut spawner = Spawner {
+        commands,
+        map_data,
+        meshes,
+        render_res,
+        monster_defs,
+        weapon_defs,
+    };
+    let mut minion_count = minion_query.iter().count();
+    let rng = &mut rng.0;
+
+    for (mut boss, ai, stats, collider) in boss_query.iter_mut() {
+        if matches!(ai.state(), AIState::PlayerUnknown) {
+            continue; // Only summon during the fight
+        }
+        if !boss.summon_timer.tick(time.delta()).finished() {
+            continue;
+        }
+        boss.summon_timer.reset();
+
+        let Some(monster_type) = stats.monster_type else {continue;};
+        let Some(def) = &spawner.monster_defs.get(monster_type).boss else {continue;};
+        let summons: Vec<_> = def.phases[boss.phase]
+            .summons
+            .iter()
+            .map(|name| {
+                spawner
+                    .monster_defs
+                    .find(name)
+                    .expect("Summons are checked when loading the monsters")
+            })
+            .collect();
+
+        let centre = Coords::from_vec(collider.pos);
+        for summon in summons {
+            if minion_count >= MAX_MINIONS {
+                break;
+            }
+            let Some(pos) = choose_summon_pos(&spawner.map_data, centre, rng) else {break;};
+
+            let minion = spawner.spawn_monster_at_pos(pos, summon, rng);
+            spawner.commands.entity(minion).insert(Minion);
+            minion_count += 1;
+        }
+    }
+}
+
+fn choose_summon_pos(
+    map_data: &crate::map::MapData,
+    centre: Coords,
+    rng: &mut fastrand::Rng,
+) -> Option<Coords> {
+    let r = SUMMON_RADIUS;
+    for _ in 0..32 {
+        let pos = Coords::new(centre.x + rng.i32(-r..=r), centre.z + rng.i32(-r..=r));
+        if !map_data.solid_map.contains_coord(pos.x, pos.z) {
+            continue;
+        }
+
+        if !map_data.solid_map[pos]
+            && !map_data.monster_map[pos]
+            && map_data.line_of_sight(centre.to_vec(0.5), pos.to_vec(0.5))
+        {
+            return Some(pos);
+        }
+    }
+    None
+}
+
+/// The game is won once both the boss and its phylactery are destroyed.
+pub fn check_victory(
+    mut game: ResMut<crate::GameInfo>,
+    boss_query: Query<(), With<Boss>>,
+    phylactery_query: Query<(), With<Phylactery>>,
+    mut game_state: ResMut<NextState<crate::game::GameState>>,
+    mut menu_info: ResMut<MenuInfo>,
+) {
+    if !game.boss_fight || !boss_query.is_empty() || !phylactery_query.is_empty() {
+        return;
+    }
+
+    game.boss_fight = false;
+    game.won = true;
+    game_state.set(crate::game::GameState::GameMenu);
+    menu_info.set(MenuType::Victory);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phase(hp: f32) -> BossPhase {
+        BossPhase {
+            hp,
+            weapon: String::new(),
+            summons: vec![],
+            summon_interval: 0.0,
+        }
+    }
+
+    #[test]
+    fn phases_by_hp() {
+        let def = BossDef {
+            phases: vec![phase(1.0), phase(0.6), phase(0.3)],
+        };
+        assert!(def.is_valid());
+        assert_eq!(def.phase_at(100, 100), 0);
+        assert_eq!(def.phase_at(61, 100), 0);
+        assert_eq!(def.phase_at(60, 100), 1);
+        assert_eq!(def.phase_at(31, 100), 1);
+        assert_eq!(def.phase_at(1, 100), 2);
+    }
+
+    #[test]
+    fn invalid_phases() {
+        let def = BossDef { phases: vec![] };
+        assert!(!def.is_valid());
+
+        let def = BossDef {
+            phases: vec![phase(1.0), phase(0.3), phase(0.6)],
+        };
+        assert!(!def.is_valid());
+    }
+}
@@ -0,0 +1,78 @@
+use bevy::prelude::*;
+
+use super::{
+    ai::{AiMover, AI},
+    boss::{Boss, Minion, Phylactery},
+    monsterdefs::MonsterDefs,
+    status::StatusEffects,
+    weapon::Weapon,
+    CreatureStats, MonsterType,
+};
+use crate::{physics::Collider, render::Animation};
+
+/// Time each death frame is shown.
+const DEATH_FRAME_TIME: f32 = 0.15;
+
+/// A killed monster that is playing its death animation. It can't move, attack or be hit anymore.
+#[derive(Component)]
+pub struct Dying {
+    monster_type: MonsterType,
+    timer: Option<Timer>,
+}
+
+/// Turns a killed monster into a dying one. `update_dying` takes it from there.
+pub fn start_dying(commands: &mut Commands, entity: Entity, monster_type: MonsterType) {
+    commands
+        .entity(entity)
+        .remove::<(
+            AI,
+            AiMover,
+            CreatureStats,
+            Weapon,
+            StatusEffects,
+            Collider,
+            Boss,
+            Phylactery,
+            Minion,
+        )>()
+        .insert(Dying {
+            monster_type,
+            timer: None,
+        });
+}
+
+pub fn update_dying(
+    mut commands: Commands,
+    time: Res<Time>,
+    monster_defs: Res<MonsterDefs>,
+    render_res: Res<crate::render::RenderResource>,
+    mut query: Query<(Entity, &mut Dying, &mut Animation)>,
+) {
+    for (entity, mut dying, mut animation) in query.iter_mut() {
+        let def = monster_defs.get(dying.monster_type);
+
+        let Some(timer) = &mut dying.timer else {
+            let Some(frames) = def.get_death_seq(&render_res.sprites) else {
+                commands.entity(entity).despawn();
+                continue;
+            };
+
+            // Wait half a frame longer, so the last frame is shown when the animation is removed
+            let duration = (frames.x.len() as f32 + 0.5) * DEATH_FRAME_TIME;
+            *animation = Animation::once(frames, DEATH_FRAME_TIME);
+            dying.timer = Some(Timer::from_seconds(duration, TimerMode::Once));
+            continue;
+        };
+
+        if !timer.tick(time.delta()).finished() {
+            continue;
+        }
+
+        if def.corpse {
+            // The last frame stays behind as a corpse. It is a level object, so it is removed with the level.
+            commands.entity(entity).remove::<(Dying, Animation)>();
+        } else {
+            commands.entity(entity).despawn();
+        }
+    }
+}
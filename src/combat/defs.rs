@@ -0,0 +1,83 @@
+use serde::de::DeserializeOwned;
+
+use crate::mapgen::style::LevelStyle;
+
+/// Why a definition file in `assets/data` could not be loaded.
+#[derive(Debug)]
+pub enum DefError {
+    IO(std::io::Error),
+    Parse(ron::error::SpannedError),
+    TooManyDefs,
+    DuplicateName(String),
+    /// `def` refers to a `kind` of thing called `name`, which doesn't exist
+    Unknown {
+        def: String,
+        kind: &'static str,
+        name: String,
+    },
+    InvalidBoss(String),
+    NoMonsters(LevelStyle),
+    CorpseWithoutDeathFrames(String),
+    EmptyLoadout,
+}
+
+impl std::fmt::Display for DefError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IO(err) => write!(f, "Could not read the file: {}", err),
+            Self::Parse(err) => write!(f, "Could not parse the file: {}", err),
+            Self::TooManyDefs => write!(f, "Too many definitions"),
+            Self::DuplicateName(name) => write!(f, "`{}` is defined twice", name),
+            Self::Unknown { def, kind, name } => {
+                write!(f, "`{}` uses unknown {} `{}`", def, kind, name)
+            }
+            Self::InvalidBoss(name) => write!(
+                f,
+                "Boss `{}` should have phases with decreasing HP, starting at 1.0",
+                name
+            ),
+            Self::NoMonsters(level) => write!(f, "Level {:?} has no monsters", level),
+            Self::CorpseWithoutDeathFrames(name) => {
+                write!(
+                    f,
+                    "Monster `{}` leaves a corpse, but has no death frames",
+                    name
+                )
+            }
+            Self::EmptyLoadout => write!(f, "The player loadout is empty"),
+        }
+    }
+}
+
+/// Reads the definition file at `path` and hands its text to `parse`.
+pub fn load<T>(path: &str, parse: impl FnOnce(&str) -> Result<T, DefError>) -> Result<T, DefError> {
+    let text = std::fs::read_to_string(path).map_err(DefError::IO)?;
+    parse(&text)
+}
+
+pub fn from_ron<T: DeserializeOwned>(text: &str) -> Result<T, DefError> {
+    ron::from_str(text).map_err(DefError::Parse)
+}
+
+/// Fails on the first name that is used twice. The definitions are looked up by name, so each has to be unique.
+pub fn check_unique<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<(), DefError> {
+    let mut seen = std::collections::HashSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            return Err(DefError::DuplicateName(name.to_string()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_name() {
+        assert!(check_unique(["imp", "goblin", "lich"]).is_ok());
+        let result = check_unique(["imp", "goblin", "imp"]);
+        assert!(matches!(result, Err(DefError::DuplicateName(name)) if name == "imp"));
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+
+use super::{
+    projectile::spawn_projectile,
+    weapon::{Weapon, WeaponEffect},
+    weapondefs::WeaponDefs,
+    CreatureStats, DamageEvent, DamageType, Team,
+};
+use crate::{grid::Coords, map::MapData, physics::Collider};
+
+/// Time between two ticks of lava damage.
+const LAVA_TICK: f32 = 0.5;
+const LAVA_DAMAGE: i16 = 4;
+
+/// A dart launcher hidden in a wall. It keeps firing across the corridor in front of it.
+/// The darts are owned by the environment, so they hurt players and monsters alike.
+#[derive(Component)]
+pub struct DartTrap {
+    pub dir: Vec3,
+}
+
+#[derive(Resource)]
+pub struct LavaTimer(Timer);
+
+impl Default for LavaTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(LAVA_TICK, TimerMode::Repeating))
+    }
+}
+
+pub fn fire_dart_traps(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut trap_query: Query<(Entity, &DartTrap, &mut Weapon, &Transform)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut render_res: ResMut<crate::render::RenderResource>,
+    weapon_defs: Res<WeaponDefs>,
+) {
+    for (entity, trap, mut weapon, transform) in trap_query.iter_mut() {
+        if !weapon.cooldown.tick(time.delta()).finished() {
+            continue;
+        }
+        weapon.cooldown.reset();
+
+        let WeaponEffect::Ranged { ptype, .. } = weapon.effect else {continue;};
+        spawn_projectile(
+            entity,
+            Team::Environment,
+            transform.translation,
+            trap.dir,
+            &weapon,
+            weapon_defs.projectile(ptype),
+            &mut commands,
+            &mut meshes,
+            &mut render_res,
+        );
+    }
+}
+
+/// Creatures standing in lava get burned, unless they are immune to fire.
+pub fn lava_damage(
+    time: Res<Time>,
+    mut timer: ResMut<LavaTimer>,
+    map_data: Res<MapData>,
+    creature_query: Query<(Entity, &Collider), With<CreatureStats>>,
+    mut ev_damage: EventWriter<DamageEvent>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    for (entity, collider) in creature_query.iter() {
+        let pos = Coords::from_vec(collider.pos);
+        if !map_data.tile_map.contains_coord(pos.x, pos.z) || !map_data.tile_map[pos].is_lava() {
+            continue;
+        }
+
+        ev_damage.send(DamageEvent {
+            instigator: None,
+            target: entity,
+            damage: LAVA_DAMAGE,
+            dam_type: DamageType::Fire,
+        });
+    }
+}
@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+
+use super::projectile::ProjectileDef;
+use crate::{map::MapData, physics::Collider, render::RenderResource};
+
+/// Hitscan weapons without a range stop at this distance, in tiles
+const MAX_RANGE: f32 = 32.0;
+/// How long the impact sprite is shown
+const IMPACT_TIME: f32 = 0.3;
+
+/// Where a hitscan shot ended, and the creature it hit, if any.
+#[derive(Debug, PartialEq)]
+pub struct Hit {
+    pub pos: Vec3,
+    pub target: Option<Entity>,
+}
+
+/// Traces a shot from `origin` in the normalized direction `dir`. It stops at the first wall in `MapData::los_map`
+/// or the first of the `targets` along the way, whichever comes first.
+pub fn trace<'a>(
+    map_data: &MapData,
+    origin: Vec3,
+    dir: Vec3,
+    range: f32,
+    targets: impl IntoIterator<Item = (Entity, &'a Collider)>,
+) -> Hit {
+    let range = range.min(MAX_RANGE);
+    let mut dist = map_data
+        .first_blocked(origin, origin + dir * range)
+        .map_or(range, |t| t * range);
+
+    let mut target = None;
+    for (entity, collider) in targets {
+        if let Some(hit_dist) = collider.ray_hit(origin, dir) {
+            if hit_dist < dist {
+                dist = hit_dist;
+                target = Some(entity);
+            }
+        }
+    }
+
+    Hit {
+        pos: origin + dir * dist,
+        target,
+    }
+}
+
+/// Shows the animated sprite of `impact_def` at `pos` for a moment.
+pub fn spawn_impact(
+    pos: Vec3,
+    impact_def: &ProjectileDef,
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    render_res: &mut ResMut<RenderResource>,
+) {
+    let uv = impact_def.make_uv(&render_res.sprites);
+
+    commands
+        .spawn(uv.to_sprite_bundle(pos, meshes, render_res))
+        .insert(crate::render::Animation::new(uv, 0.1))
+        .insert(crate::lifecycle::Ttl::new(IMPACT_TIME));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Grid;
+
+    /// A 10x10 room, split by a wall at x = 6.
+    fn map_data() -> MapData {
+        let mut los_map = Grid::new_from(10, 10, false);
+        for (pos, solid) in los_map.iter_mut() {
+            *solid = pos.x == 0 || pos.x == 9 || pos.z == 0 || pos.z == 9 || pos.x == 6;
+        }
+        MapData {
+            los_map,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hits_wall() {
+        let hit = trace(&map_data(), Vec3::new(1.5, 0.5, 4.5), Vec3::X, 20.0, []);
+        assert_eq!(hit.target, None);
+        assert!((hit.pos.x - 6.0).abs() < 1e-4);
+
+        // Out of range before reaching the wall
+        let hit = trace(&map_data(), Vec3::new(1.5, 0.5, 4.5), Vec3::X, 2.0, []);
+        assert!((hit.pos.x - 3.5).abs() < 1e-4);
+
+        let hit = trace(&map_data(), Vec3::new(8.5, 0.5, 4.5), Vec3::NEG_X, 20.0, []);
+        assert!((hit.pos.x - 7.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn hits_first_creature() {
+        let near = Entity::from_raw(1);
+        let far = Entity::from_raw(2);
+        let behind_wall = Entity::from_raw(3);
+        let colliders = [
+            (far, Collider::new(Vec3::new(4.5, 0.5, 4.6), 0.3)),
+            (near, Collider::new(Vec3::new(3.0, 0.5, 4.4), 0.3)),
+            (behind_wall, Collider::new(Vec3::new(7.5, 0.5, 4.5), 0.3)),
+        ];
+        let targets = || colliders.iter().map(|(e, c)| (*e, c));
+        let map_data = map_data();
+        let origin = Vec3::new(1.5, 0.5, 4.5);
+
+        let hit = trace(&map_data, origin, Vec3::X, 20.0, targets());
+        assert_eq!(hit.target, Some(near));
+
+        // Shooting away from them
+        let hit = trace(&map_data, origin, Vec3::NEG_X, 20.0, targets());
+        assert_eq!(hit.target, None);
+        assert!((hit.pos.x - 1.0).abs() < 1e-4);
+
+        // The wall is in the way
+        let hit = trace(&map_data, origin + Vec3::X * 4.0, Vec3::X, 20.0, targets());
+        assert_eq!(hit.target, None);
+    }
+}
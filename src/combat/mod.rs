@@ -1,16 +1,28 @@
+use std::num::NonZeroU8;
+
 use bevy::prelude::*;
+use serde::Deserialize;
 
 use crate::{
-    game::GameState,
+    game::{GameState, TickSet},
     ui::menus::{MenuInfo, MenuType},
 };
 
-use self::ai::AiMover;
+use self::{ai::AiMover, monsterdefs::MonsterDefs};
 
 pub mod ai;
+pub mod arsenal;
+pub mod boss;
+pub mod death;
+pub mod defs;
+pub mod hazard;
+pub mod hitscan;
+pub mod monsterdefs;
 pub mod player;
 pub mod projectile;
+pub mod status;
 pub mod weapon;
+pub mod weapondefs;
 
 pub struct CombatPlugin;
 
@@ -18,56 +30,129 @@ impl Plugin for CombatPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.insert_resource(player::InputMap::default())
             .insert_resource(player::InputState::default())
+            .insert_resource(ai::FlowFields::default())
+            .insert_resource(hazard::LavaTimer::default())
+            .insert_resource(player::TickInput::default())
+            .insert_resource(CombatRng::default())
             .add_event::<DamageEvent>()
+            .add_event::<NoiseEvent>()
+            .add_event::<DeathEvent>()
+            .add_event::<KnockbackEvent>()
             .add_event::<player::InputAction>()
+            .add_systems(
+                Startup,
+                (
+                    weapondefs::load_weapon_defs,
+                    apply_deferred, // The monsters refer to the weapons
+                    monsterdefs::load_monster_defs,
+                )
+                    .chain(),
+            )
+            .add_systems(OnEnter(GameState::InGame), ai::reset_flow_fields)
             .add_systems(
                 Update,
                 (
                     player::gamepad_connections,
-                    player::get_player_input,
+                    player::get_player_input.run_if(crate::replay::live_input),
+                    status::update_status_tint,
+                )
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    player::send_tick_input
+                        .run_if(crate::replay::live_input)
+                        .in_set(TickSet::Input),
                     (
+                        arsenal::switch_weapons,
+                        player::handle_player_interactions,
                         player::handle_player_move,
                         player::handle_player_rotate,
-                        player::handle_player_interactions,
+                        player::accelerate_player,
+                        player::update_map,
                     )
-                        .after(player::get_player_input),
-                    player::update_map,
-                    ai::ai_los.after(player::update_map),
-                    ai::ai_move.after(ai::ai_los),
-                    projectile::check_collisions,
-                    projectile::take_damage_system
-                        .after(projectile::check_collisions)
-                        .after(weapon::fire_weapons),
-                    weapon::fire_weapons
-                        .after(player::handle_player_interactions)
-                        .after(ai::ai_move),
-                )
-                    .run_if(in_state(GameState::InGame)),
+                        .chain()
+                        .in_set(TickSet::Player),
+                    (
+                        ai::ai_los,
+                        ai::update_flow_fields,
+                        ai::ai_alert,
+                        ai::ai_hear,
+                        ai::ai_move,
+                    )
+                        .chain()
+                        .in_set(TickSet::Ai),
+                    (
+                        projectile::home_projectiles,
+                        projectile::check_collisions,
+                        projectile::projectile_hit_walls,
+                        hazard::fire_dart_traps,
+                        hazard::lava_damage,
+                        status::tick_status_effects,
+                        weapon::fire_weapons,
+                        projectile::take_damage_system,
+                    )
+                        .chain()
+                        .in_set(TickSet::Combat),
+                    (
+                        boss::update_boss_shield,
+                        boss::update_boss_phases,
+                        boss::summon_minions,
+                        boss::check_victory,
+                        death::update_dying,
+                        ai::apply_knockback,
+                    )
+                        .chain()
+                        .in_set(TickSet::Aftermath),
+                ),
             );
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum MonsterType {
-    Imp = 1,
-    Goblin,
-    EyeMonster1,
-    EyeMonster2,
-    Ettin,
-    Laima,
-    Snowman,
-    IronGolem,
-    Demon,
-}
+/// Refers to a monster archetype in `MonsterDefs`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MonsterType(NonZeroU8);
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub enum Team {
     Players,
     Monsters,
+    /// Owns traps and other hazards. It is hostile to everybody.
     Environment,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// How creatures of two teams treat each other.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Relation {
+    /// Attack each other on sight
+    Hostile,
+    /// Don't attack each other. Stray shots only hurt with infighting turned on.
+    Neutral,
+    /// Never hurt each other
+    Allied,
+}
+
+impl Team {
+    pub const fn relation(self, other: Team) -> Relation {
+        match (self, other) {
+            (Team::Environment, _) | (_, Team::Environment) => Relation::Hostile,
+            (Team::Players, Team::Players) => Relation::Allied,
+            (Team::Monsters, Team::Monsters) => Relation::Neutral,
+            (Team::Players, Team::Monsters) | (Team::Monsters, Team::Players) => Relation::Hostile,
+        }
+    }
+
+    pub fn can_hurt(self, other: Team, infighting: bool) -> bool {
+        match self.relation(other) {
+            Relation::Hostile => true,
+            Relation::Neutral => infighting,
+            Relation::Allied => false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
 pub enum DamageType {
     Normal,
     Fire,
@@ -75,6 +160,51 @@ pub enum DamageType {
     Cold,
 }
 
+/// Multipliers for incoming damage. 1.0 is normal damage, 0.0 is immunity and values above 1.0 are weaknesses.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Resistances {
+    pub normal: f32,
+    pub fire: f32,
+    pub electric: f32,
+    pub cold: f32,
+}
+
+impl Default for Resistances {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+impl Resistances {
+    pub const NONE: Self = Self {
+        normal: 1.0,
+        fire: 1.0,
+        electric: 1.0,
+        cold: 1.0,
+    };
+
+    pub fn get(&self, dam_type: DamageType) -> f32 {
+        match dam_type {
+            DamageType::Normal => self.normal,
+            DamageType::Fire => self.fire,
+            DamageType::Electric => self.electric,
+            DamageType::Cold => self.cold,
+        }
+    }
+
+    /// Every hit does at least one damage, unless the creature is immune.
+    pub fn apply(&self, damage: i16, dam_type: DamageType) -> i16 {
+        let multiplier = self.get(dam_type);
+        if damage <= 0 || multiplier <= 0.0 {
+            return 0;
+        }
+
+        ((damage as f32 * multiplier).round() as i16).max(1)
+    }
+}
+
+/// Damage without an instigator, like burning, doesn't cause new status effects.
 #[derive(Event, Debug, PartialEq, Eq)]
 pub struct DamageEvent {
     pub instigator: Option<Entity>,
@@ -83,6 +213,32 @@ pub struct DamageEvent {
     pub dam_type: DamageType,
 }
 
+/// Sent by heavy hits, to push the target away in `dir`.
+#[derive(Event, Debug, PartialEq)]
+pub struct KnockbackEvent {
+    pub target: Entity,
+    pub dir: Vec3,
+}
+
+/// Sent when a monster is killed.
+#[derive(Event, Debug, PartialEq)]
+pub struct DeathEvent {
+    pub pos: Vec3,
+    pub monster_type: MonsterType,
+}
+
+/// Sound made by combat, which monsters can hear. The loudness is the distance in tiles the noise travels.
+#[derive(Event, Debug, PartialEq)]
+pub struct NoiseEvent {
+    pub pos: Vec3,
+    pub loudness: f32,
+}
+
+/// Rolls everything random in a fight, like weapon spread and where monsters wander. It is seeded by the level, so
+/// the same seed and input always play out the same way.
+#[derive(Resource, Default)]
+pub struct CombatRng(pub fastrand::Rng);
+
 #[derive(Component)]
 pub struct CreatureStats {
     pub speed: f32,
@@ -90,6 +246,7 @@ pub struct CreatureStats {
     pub hp_max: i16,
     pub team: Team,
     pub monster_type: Option<MonsterType>,
+    pub resistances: Resistances,
 }
 
 impl CreatureStats {
@@ -100,32 +257,47 @@ impl CreatureStats {
             hp_max: 60,
             team: Team::Players,
             monster_type: None,
+            resistances: Resistances::NONE,
         }
     }
 
+    /// The damage taken from a hit after resistances.
+    pub fn final_damage(&self, damage: i16, dam_type: DamageType) -> i16 {
+        self.resistances.apply(damage, dam_type)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn take_damage(
         &mut self,
         evt: &DamageEvent,
         commands: &mut Commands,
         game: &mut ResMut<crate::GameInfo>,
+        monster_defs: &MonsterDefs,
         game_state: &mut ResMut<NextState<crate::game::GameState>>,
         map_data: &mut ResMut<crate::map::MapData>,
         menu_info: &mut ResMut<MenuInfo>,
         ai_pos: Option<&mut AiMover>,
     ) -> bool {
-        if evt.damage <= 0 {
+        if !self.alive() {
+            return false; // Already dying, which should only start once
+        }
+
+        let damage = self.final_damage(evt.damage, evt.dam_type);
+        if damage <= 0 {
             return false;
         }
 
-        self.hp -= evt.damage;
+        self.hp -= damage;
         if !self.alive() {
             if self.team == Team::Players {
                 game_state.set(crate::game::GameState::GameMenu);
                 menu_info.set(MenuType::GameOver);
             } else {
-                commands.entity(evt.target).despawn();
                 if let Some(monster_type) = self.monster_type {
-                    game.score += monster_type.get_score();
+                    game.score += monster_defs.get(monster_type).score;
+                    death::start_dying(commands, evt.target, monster_type);
+                } else {
+                    commands.entity(evt.target).despawn();
                 }
 
                 if let Some(ai_pos) = ai_pos {
@@ -152,7 +324,7 @@ impl CreatureStats {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use super::{monsterdefs::tests::monster_stats, *};
 
     #[test]
     fn monster_null_optimization() {
@@ -161,4 +333,44 @@ mod tests {
             std::mem::size_of::<MonsterType>()
         );
     }
+
+    #[test]
+    fn team_relations() {
+        let teams = [Team::Players, Team::Monsters, Team::Environment];
+        for t0 in teams {
+            for t1 in teams {
+                assert_eq!(t0.relation(t1), t1.relation(t0));
+            }
+            assert!(Team::Environment.can_hurt(t0, false));
+        }
+
+        assert!(!Team::Players.can_hurt(Team::Players, true));
+        assert!(Team::Monsters.can_hurt(Team::Monsters, true));
+        assert!(!Team::Monsters.can_hurt(Team::Monsters, false));
+        assert_eq!(Team::Monsters.relation(Team::Monsters), Relation::Neutral);
+        assert_eq!(Team::Monsters.relation(Team::Players), Relation::Hostile);
+    }
+
+    #[test]
+    fn resistances() {
+        let snowman = monster_stats("snowman");
+        assert_eq!(snowman.final_damage(5, DamageType::Normal), 5);
+        assert_eq!(snowman.final_damage(5, DamageType::Fire), 10);
+        assert_eq!(snowman.final_damage(5, DamageType::Cold), 0);
+
+        let golem = monster_stats("iron_golem");
+        assert_eq!(golem.final_damage(5, DamageType::Normal), 3);
+        assert_eq!(golem.final_damage(5, DamageType::Electric), 10);
+
+        let demon = monster_stats("demon");
+        assert_eq!(demon.final_damage(100, DamageType::Fire), 0);
+    }
+
+    #[test]
+    fn minimum_damage() {
+        let golem = monster_stats("iron_golem");
+        assert_eq!(golem.final_damage(1, DamageType::Normal), 1);
+        assert_eq!(golem.final_damage(0, DamageType::Normal), 0);
+        assert_eq!(golem.final_damage(-3, DamageType::Electric), 0);
+    }
 }
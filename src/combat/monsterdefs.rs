@@ -0,0 +1,371 @@
+use std::{collections::HashMap, num::NonZeroU8, path::Path};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{
+    ai::{AiDef, AI},
+    boss::BossDef,
+    defs::{self, DefError},
+    weapon::Weapon,
+    weapondefs::WeaponDefs,
+    CreatureStats, MonsterType, Resistances, Team,
+};
+use crate::{items::loot::LootDrop, mapgen::style::LevelStyle, render::spritemap::SpriteSeq};
+
+const MONSTER_DEFS_PATH: &str = "./assets/data/monsters.ron";
+const MONSTER_SPRITE_DIR: &str = "./assets/monsters";
+
+/// A monster archetype, as defined in `assets/data/monsters.ron`.
+#[derive(Deserialize)]
+pub struct MonsterDef {
+    pub name: String,
+    pub sprite: String,
+    /// The last frames of the sprite sheet show the monster dying. Without them, it disappears at once.
+    #[serde(default)]
+    pub death_frames: u8,
+    /// Whether the last death frame stays behind as a corpse
+    #[serde(default)]
+    pub corpse: bool,
+    pub speed: f32,
+    pub hp: i16,
+    pub score: i32,
+    #[serde(default)]
+    pub jumps: bool,
+    pub ai: AiDef,
+    #[serde(default)]
+    pub resistances: Resistances,
+    /// The name of the weapon in `assets/data/weapons.ron`. Monsters without a weapon don't attack.
+    #[serde(default)]
+    pub weapon: Option<String>,
+    #[serde(default)]
+    pub boss: Option<BossDef>,
+    #[serde(default)]
+    pub loot: Vec<LootDrop>,
+}
+
+impl MonsterDef {
+    pub fn make_ai(&self) -> AI {
+        self.ai.make_ai()
+    }
+
+    pub fn make_stats(&self, monster_type: MonsterType) -> CreatureStats {
+        CreatureStats {
+            speed: self.speed,
+            hp: self.hp,
+            hp_max: self.hp,
+            team: Team::Monsters,
+            monster_type: Some(monster_type),
+            resistances: self.resistances,
+        }
+    }
+
+    pub fn make_weapon(&self, weapon_defs: &WeaponDefs) -> Option<Weapon> {
+        let weapon = self.weapon.as_ref()?;
+        let weapon_type = weapon_defs
+            .find(weapon)
+            .expect("Weapons are checked when loading the monsters");
+        Some(weapon_defs.make_weapon(weapon_type))
+    }
+
+    /// The walking frames of the monster.
+    pub fn get_tile_seq(&self, tiles: &crate::render::spritemap::SpriteMap) -> SpriteSeq {
+        let mut seq = tiles.get_monster(&self.sprite);
+        seq.x.end -= self.death_frame_count(&seq);
+        seq
+    }
+
+    pub fn get_death_seq(&self, tiles: &crate::render::spritemap::SpriteMap) -> Option<SpriteSeq> {
+        let mut seq = tiles.get_monster(&self.sprite);
+        let count = self.death_frame_count(&seq);
+        if count == 0 {
+            return None;
+        }
+        seq.x.start = seq.x.end - count;
+        Some(seq)
+    }
+
+    /// At least one walking frame is kept, in case the sprite sheet is too small.
+    fn death_frame_count(&self, seq: &SpriteSeq) -> u8 {
+        let frames = seq.x.len() as u8;
+        if self.death_frames >= frames {
+            warn!(
+                "Monster {} has not enough frames for its death animation",
+                self.name
+            );
+            return 0;
+        }
+        self.death_frames
+    }
+}
+
+#[derive(Deserialize)]
+struct MonsterDefsFile {
+    monsters: Vec<MonsterDef>,
+    levels: HashMap<LevelStyle, Vec<String>>,
+}
+
+/// All monster archetypes, indexed by `MonsterType`.
+#[derive(Resource, Default)]
+pub struct MonsterDefs {
+    defs: Vec<MonsterDef>,
+    levels: HashMap<LevelStyle, Vec<MonsterType>>,
+}
+
+impl MonsterDefs {
+    pub fn load(weapon_defs: &WeaponDefs) -> Result<Self, DefError> {
+        defs::load(MONSTER_DEFS_PATH, |text| {
+            Self::parse(text, weapon_defs, |sprite| {
+                Path::new(MONSTER_SPRITE_DIR).join(sprite).is_file()
+            })
+        })
+    }
+
+    /// Parses and validates monster definitions. `sprite_exists` checks whether a sprite name refers to a monster sprite.
+    pub fn parse(
+        text: &str,
+        weapon_defs: &WeaponDefs,
+        sprite_exists: impl Fn(&str) -> bool,
+    ) -> Result<Self, DefError> {
+        let file: MonsterDefsFile = defs::from_ron(text)?;
+
+        if file.monsters.len() >= u8::MAX as usize {
+            return Err(DefError::TooManyDefs);
+        }
+        defs::check_unique(file.monsters.iter().map(|def| def.name.as_str()))?;
+
+        let mut defs = Self {
+            defs: file.monsters,
+            levels: HashMap::new(),
+        };
+
+        for def in &defs.defs {
+            if !sprite_exists(&def.sprite) {
+                return Err(DefError::Unknown {
+                    def: def.name.clone(),
+                    kind: "sprite",
+                    name: def.sprite.clone(),
+                });
+            }
+
+            if def.corpse && def.death_frames == 0 {
+                return Err(DefError::CorpseWithoutDeathFrames(def.name.clone()));
+            }
+
+            let check_weapon = |weapon: &String| {
+                if weapon_defs.find(weapon).is_some() {
+                    Ok(())
+                } else {
+                    Err(DefError::Unknown {
+                        def: def.name.clone(),
+                        kind: "weapon",
+                        name: weapon.clone(),
+                    })
+                }
+            };
+
+            if let Some(weapon) = &def.weapon {
+                check_weapon(weapon)?;
+            }
+
+            if let Some(boss) = &def.boss {
+                if !boss.is_valid() {
+                    return Err(DefError::InvalidBoss(def.name.clone()));
+                }
+
+                for phase in &boss.phases {
+                    check_weapon(&phase.weapon)?;
+                    for summon in &phase.summons {
+                        if defs.find(summon).is_none() {
+                            return Err(DefError::Unknown {
+                                def: def.name.clone(),
+                                kind: "monster",
+                                name: summon.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        for level in crate::mapgen::style::BASE_LEVELS
+            .iter()
+            .chain(crate::mapgen::style::ALT_LEVELS.iter())
+        {
+            let names = file.levels.get(level).map_or(&[][..], |n| n.as_slice());
+            if names.is_empty() {
+                return Err(DefError::NoMonsters(*level));
+            }
+
+            let monsters = names
+                .iter()
+                .map(|name| {
+                    defs.find(name).ok_or_else(|| DefError::Unknown {
+                        def: format!("{:?}", level),
+                        kind: "monster",
+                        name: name.clone(),
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            defs.levels.insert(*level, monsters);
+        }
+
+        Ok(defs)
+    }
+
+    pub fn find(&self, name: &str) -> Option<MonsterType> {
+        let index = self.defs.iter().position(|def| def.name == name)?;
+        Some(MonsterType(NonZeroU8::new(index as u8 + 1).unwrap()))
+    }
+
+    pub fn get(&self, monster_type: MonsterType) -> &MonsterDef {
+        &self.defs[monster_type.0.get() as usize - 1]
+    }
+
+    /// The monsters that spawn in the given level style. The ones at the front are more common.
+    pub fn level_monsters(&self, level: LevelStyle) -> &[MonsterType] {
+        &self.levels[&level]
+    }
+}
+
+pub fn load_monster_defs(mut commands: Commands, weapon_defs: Res<WeaponDefs>) {
+    match MonsterDefs::load(&weapon_defs) {
+        Ok(defs) => commands.insert_resource(defs),
+        Err(err) => panic!("Loading {} failed. {}", MONSTER_DEFS_PATH, err),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::combat::weapondefs::tests::weapon_defs;
+
+    /// The monster definitions as shipped with the game.
+    pub fn monster_defs() -> MonsterDefs {
+        MonsterDefs::load(&weapon_defs()).expect("The shipped monster definitions should be valid")
+    }
+
+    /// Stats of the named monster from the shipped definitions.
+    pub fn monster_stats(name: &str) -> CreatureStats {
+        let defs = monster_defs();
+        let monster_type = defs.find(name).unwrap();
+        defs.get(monster_type).make_stats(monster_type)
+    }
+
+    fn with_monsters(monsters: &str) -> String {
+        format!(
+            "(monsters: [{}], levels: {{ {} }})",
+            monsters,
+            crate::mapgen::style::BASE_LEVELS
+                .iter()
+                .chain(crate::mapgen::style::ALT_LEVELS.iter())
+                .map(|level| format!("{:?}: [\"imp\"]", level))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    const IMP: &str = r#"(
+        name: "imp",
+        sprite: "imp.png",
+        speed: 2.5,
+        hp: 5,
+        score: 20,
+        ai: (flags: "Approach | Follow"),
+        weapon: Some("imp_claws"),
+    )"#;
+
+    #[test]
+    fn shipped_defs() {
+        let defs = monster_defs();
+        let goblin = defs.find("goblin").unwrap();
+        assert_eq!(defs.get(goblin).name, "goblin");
+        assert!(!defs.get(goblin).loot.is_empty());
+        assert!(defs.level_monsters(LevelStyle::Castle).contains(&goblin));
+        assert_eq!(defs.find("dragon"), None);
+
+        let lich = defs.get(defs.find("lich").unwrap());
+        assert_eq!(lich.boss.as_ref().unwrap().phases.len(), 3);
+    }
+
+    #[test]
+    fn parse_minimal() {
+        let defs = MonsterDefs::parse(&with_monsters(IMP), &weapon_defs(), |_| true).unwrap();
+        let imp = defs.find("imp").unwrap();
+        assert_eq!(defs.get(imp).resistances, Resistances::NONE);
+        assert_eq!(defs.level_monsters(LevelStyle::Ice), &[imp]);
+    }
+
+    #[test]
+    fn unknown_sprite() {
+        let result = MonsterDefs::parse(&with_monsters(IMP), &weapon_defs(), |sprite| {
+            sprite != "imp.png"
+        });
+        assert!(matches!(
+            result,
+            Err(DefError::Unknown { kind: "sprite", .. })
+        ));
+    }
+
+    #[test]
+    fn unknown_weapon() {
+        let text = with_monsters(&IMP.replace("imp_claws", "laser"));
+        let result = MonsterDefs::parse(&text, &weapon_defs(), |_| true);
+        assert!(matches!(
+            result,
+            Err(DefError::Unknown { kind: "weapon", .. })
+        ));
+    }
+
+    #[test]
+    fn boss_phases() {
+        let boss = IMP.replace(
+            "weapon:",
+            r#"boss: Some((phases: [
+                (hp: 1.0, weapon: "imp_claws", summons: ["imp"], summon_interval: 5.0),
+                (hp: 0.5, weapon: "demon_fire"),
+            ])), weapon:"#,
+        );
+        let defs = MonsterDefs::parse(&with_monsters(&boss), &weapon_defs(), |_| true).unwrap();
+        let imp = defs.get(defs.find("imp").unwrap());
+        assert_eq!(imp.boss.as_ref().unwrap().phase_at(2, 5), 1);
+
+        let text = with_monsters(&boss.replace("summons: [\"imp\"]", "summons: [\"dragon\"]"));
+        let result = MonsterDefs::parse(&text, &weapon_defs(), |_| true);
+        assert!(matches!(
+            result,
+            Err(DefError::Unknown {
+                def,
+                kind: "monster",
+                ..
+            }) if def == "imp"
+        ));
+
+        let text = with_monsters(&boss.replace("hp: 0.5", "hp: 1.5"));
+        let result = MonsterDefs::parse(&text, &weapon_defs(), |_| true);
+        assert!(matches!(result, Err(DefError::InvalidBoss(_))));
+    }
+
+    #[test]
+    fn corpse_without_death_frames() {
+        let text = with_monsters(&IMP.replace("speed:", "corpse: true, speed:"));
+        let result = MonsterDefs::parse(&text, &weapon_defs(), |_| true);
+        assert!(matches!(result, Err(DefError::CorpseWithoutDeathFrames(_))));
+
+        let text = with_monsters(&IMP.replace("speed:", "death_frames: 2, corpse: true, speed:"));
+        assert!(MonsterDefs::parse(&text, &weapon_defs(), |_| true).is_ok());
+    }
+
+    #[test]
+    fn unknown_level_monster() {
+        let text = with_monsters(IMP).replacen("\"imp\"]", "\"dragon\"]", 1);
+        let result = MonsterDefs::parse(&text, &weapon_defs(), |_| true);
+        assert!(matches!(
+            result,
+            Err(DefError::Unknown {
+                kind: "monster",
+                ..
+            })
+        ));
+    }
+}
@@ -17,7 +17,12 @@ use crate::{
     ui::menus::{MenuInfo, MenuType},
 };
 
-use super::{projectile::ProjectileType, weapon::Weapon, CreatureStats};
+use super::{
+    arsenal::Arsenal, status::StatusEffects, weapon::Weapon, weapondefs::WeaponDefs, CreatureStats,
+};
+
+/// How much faster the player runs while sprinting
+const SPRINT_FACTOR: f32 = 1.5;
 
 #[derive(Bundle)]
 pub struct PlayerBundle {
@@ -26,24 +31,23 @@ pub struct PlayerBundle {
     pub physisc: Collider,
     pub velocity: PhysicsMovable,
     pub weapon: Weapon,
+    pub arsenal: Arsenal,
+    pub status: StatusEffects,
+    pub intent: MoveIntent,
 }
 
 impl PlayerBundle {
-    pub fn new(pos: Vec3) -> Self {
-        let mut weapon = Weapon::new_ranged(
-            0.3,
-            ProjectileType::BlueBlob,
-            12.0,
-            15,
-            super::DamageType::Normal,
-        );
-        weapon.set_fire_state(false); // Unlike AI's don't automatically fire.
+    pub fn new(pos: Vec3, weapon_defs: &WeaponDefs) -> Self {
+        let (arsenal, weapon) = Arsenal::new(weapon_defs);
 
         Self {
             player: Player {},
             stats: CreatureStats::player(),
             physisc: Collider::new(pos, 0.125),
             weapon,
+            arsenal,
+            status: StatusEffects::default(),
+            intent: MoveIntent::default(),
             velocity: PhysicsMovable::new(Vec3::ZERO, MapCollisionEvent::Stop),
         }
     }
@@ -52,6 +56,13 @@ impl PlayerBundle {
 #[derive(Component)]
 pub struct Player;
 
+/// Where the player wants to move this frame, relative to its top speed.
+#[derive(Component, Default)]
+pub struct MoveIntent {
+    pub wish: Vec3,
+    pub sprint: bool,
+}
+
 #[derive(Event, Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
 pub enum InputAction {
     #[default]
@@ -69,11 +80,22 @@ pub enum InputAction {
     Fire,
     Interact,
     Pause,
+    NextWeapon,
+    PrevWeapon,
+    SelectWeapon(u8),
+    Sprint,
 }
 
 impl InputAction {
     fn fire_once(&self) -> bool {
-        matches!(self, Self::Interact | Self::Pause)
+        matches!(
+            self,
+            Self::Interact
+                | Self::Pause
+                | Self::NextWeapon
+                | Self::PrevWeapon
+                | Self::SelectWeapon(_)
+        )
     }
 }
 
@@ -108,6 +130,15 @@ impl Default for InputMap {
                 (KeyCode::ControlRight, InputAction::Fire),
                 (KeyCode::Space, InputAction::Interact),
                 (KeyCode::Escape, InputAction::Pause),
+                (KeyCode::Q, InputAction::PrevWeapon),
+                (KeyCode::E, InputAction::NextWeapon),
+                (KeyCode::Key1, InputAction::SelectWeapon(0)),
+                (KeyCode::Key2, InputAction::SelectWeapon(1)),
+                (KeyCode::Key3, InputAction::SelectWeapon(2)),
+                (KeyCode::Key4, InputAction::SelectWeapon(3)),
+                (KeyCode::Key5, InputAction::SelectWeapon(4)),
+                (KeyCode::Key6, InputAction::SelectWeapon(5)),
+                (KeyCode::ShiftLeft, InputAction::Sprint),
             ]),
             button_rot_rate: 2.5,
             mouse_buttons: HashMap::from([
@@ -125,6 +156,9 @@ impl Default for InputMap {
                 (GamepadButtonType::DPadLeft, InputAction::Left),
                 (GamepadButtonType::DPadRight, InputAction::Right),
                 (GamepadButtonType::Start, InputAction::Pause),
+                (GamepadButtonType::LeftTrigger, InputAction::PrevWeapon), // LB
+                (GamepadButtonType::RightTrigger, InputAction::NextWeapon), // RB
+                (GamepadButtonType::LeftThumb, InputAction::Sprint),
             ]),
             pad_rot_x: GamepadAxisType::RightStickX,
             pad_rot_y: GamepadAxisType::RightStickY,
@@ -162,6 +196,24 @@ impl InputState {
     }
 }
 
+/// Input is read every frame, but the game only runs on the fixed timestep. Held actions are replaced each frame,
+/// while the ones that fire once are kept until a tick has seen them.
+#[derive(Resource, Default)]
+pub struct TickInput {
+    pub held: Vec<InputAction>,
+    pub pressed: Vec<InputAction>,
+}
+
+impl TickInput {
+    pub fn push(&mut self, act: InputAction) {
+        if act.fire_once() {
+            self.pressed.push(act);
+        } else {
+            self.held.push(act);
+        }
+    }
+}
+
 pub fn gamepad_connections(
     mut state: ResMut<InputState>,
     mut gamepad_evr: EventReader<GamepadEvent>,
@@ -210,9 +262,10 @@ pub fn get_player_input(
     key_map: Res<InputMap>,
     pad_axes: Res<Axis<GamepadAxis>>,
     pad_buttons: Res<Input<GamepadButton>>,
-    mut acts: EventWriter<InputAction>,
+    mut input: ResMut<TickInput>,
 ) {
     let state = state.as_mut();
+    input.held.clear();
 
     for ev in state.reader_motion.read(&mouse_motion) {
         state.pitch -= (key_map.mouse_rot_rate * ev.delta.y).to_radians();
@@ -225,7 +278,7 @@ pub fn get_player_input(
                 continue;
             }
 
-            acts.send(*act);
+            input.push(*act);
         }
     }
 
@@ -235,7 +288,7 @@ pub fn get_player_input(
                 continue;
             }
 
-            acts.send(*act);
+            input.push(*act);
         }
     }
 
@@ -253,7 +306,7 @@ pub fn get_player_input(
 
         if let Some(dx) = pad_axes.get(axis(gamepad, key_map.pad_move_x)) {
             if let Some(dy) = pad_axes.get(axis(gamepad, key_map.pad_move_y)) {
-                acts.send(InputAction::Move {
+                input.push(InputAction::Move {
                     right: dx,
                     forward: dy,
                 });
@@ -269,59 +322,94 @@ pub fn get_player_input(
             if (action.fire_once() && pad_buttons.just_pressed(button))
                 || (!action.fire_once() && pad_buttons.pressed(button))
             {
-                acts.send(*action);
+                input.push(*action);
             }
         }
     };
 }
 
+/// Sends the input gathered since the last tick, so the gameplay systems see it once per tick.
+pub fn send_tick_input(mut input: ResMut<TickInput>, mut acts: EventWriter<InputAction>) {
+    let input = input.as_mut();
+    acts.send_batch(input.held.iter().copied().chain(input.pressed.drain(..)));
+}
+
 pub fn handle_player_move(
     mut acts: EventReader<InputAction>,
-    mut player_query: Query<(&CreatureStats, &Transform, &mut PhysicsMovable), With<Player>>,
-    map: Res<MapData>,
+    mut player_query: Query<(&Transform, &mut MoveIntent), With<Player>>,
 ) {
     // TODO: This can probably be an `if let Ok()` instead of a loop, since the player is unique.
-    for (stats, transform, mut movable) in player_query.iter_mut() {
-        let mut velocity = Vec3::ZERO;
+    for (transform, mut intent) in player_query.iter_mut() {
+        let mut wish = Vec3::ZERO;
+        let mut sprint = false;
         let local_z = transform.local_z();
         let forward = -Vec3::new(local_z.x, 0., local_z.z);
         let right = Vec3::new(local_z.z, 0., -local_z.x);
 
         for act in acts.read() {
             match act {
-                InputAction::Forward => velocity += forward,
-                InputAction::Backward => velocity -= forward,
-                InputAction::Left => velocity -= right,
-                InputAction::Right => velocity += right,
+                InputAction::Forward => wish += forward,
+                InputAction::Backward => wish -= forward,
+                InputAction::Left => wish -= right,
+                InputAction::Right => wish += right,
                 InputAction::Move {
                     right: right_perc,
                     forward: forward_perc,
                 } => {
-                    velocity += forward * (*forward_perc) + right * -(*right_perc);
+                    wish += forward * (*forward_perc) + right * -(*right_perc);
                 }
+                InputAction::Sprint => sprint = true,
                 _ => {}
             };
         }
 
-        // == Movement ==
-        // Only normalize if the distance is above one
-
-        let tile = map.tile_map[Coords::from_vec(transform.translation)];
-
-        if tile.is_on_ice() {
-            if velocity.length_squared() > 0.2 {
-                movable.velocity = velocity.normalize() * stats.speed
-            };
+        // Only normalize if the distance is above one, so analog sticks can walk slowly
+        intent.wish = if wish.length_squared() > 1.0 {
+            wish.normalize()
         } else {
-            movable.velocity = if velocity.length_squared() > 1.0 {
-                velocity.normalize()
-            } else {
-                velocity
-            } * stats.speed;
+            wish
+        };
+        intent.sprint = sprint;
+    }
+}
+
+/// Changes the velocity of the player towards where it wants to go. It runs on the fixed timestep.
+pub fn accelerate_player(
+    time: Res<Time>,
+    map: Res<MapData>,
+    mut player_query: Query<
+        (
+            &CreatureStats,
+            &StatusEffects,
+            &MoveIntent,
+            &Collider,
+            &mut PhysicsMovable,
+        ),
+        With<Player>,
+    >,
+) {
+    for (stats, effects, intent, collider, mut movable) in player_query.iter_mut() {
+        let mut top_speed = stats.speed * effects.speed_factor();
+        if intent.sprint {
+            top_speed *= SPRINT_FACTOR;
         }
+
+        let friction = map.tile_map[Coords::from_vec(collider.pos)].friction();
+        movable.velocity = accelerate(
+            movable.velocity,
+            intent.wish * top_speed,
+            friction,
+            time.delta_seconds(),
+        );
     }
 }
 
+/// Moves `velocity` towards `target`, faster on floors with more friction, so momentum carries on slippery ones.
+/// The change is exponential, so a few long steps end up at the same velocity as many short ones.
+fn accelerate(velocity: Vec3, target: Vec3, friction: f32, dt: f32) -> Vec3 {
+    velocity.lerp(target, 1.0 - (-friction * dt).exp())
+}
+
 pub fn handle_player_rotate(
     mut acts: EventReader<InputAction>,
     mut state: ResMut<InputState>,
@@ -420,3 +508,73 @@ pub fn update_map(
     let mut camera_transform = camera_query.get_single_mut().unwrap();
     *camera_transform = *player_transform;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::FloorTile;
+
+    #[test]
+    fn presses_reach_one_tick() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut world = World::new();
+        world.init_resource::<Events<InputAction>>();
+        world.init_resource::<TickInput>();
+        let mut reader = ManualEventReader::<InputAction>::default();
+        let mut tick = |world: &mut World| {
+            world.run_system_once(send_tick_input);
+            let events = world.resource::<Events<InputAction>>();
+            reader.read(events).copied().collect::<Vec<_>>()
+        };
+
+        // Two frames of input before the next tick
+        let mut input = world.resource_mut::<TickInput>();
+        input.push(InputAction::Interact);
+        input.held.clear();
+        input.push(InputAction::Forward);
+        assert!(tick(&mut world) == [InputAction::Forward, InputAction::Interact]);
+
+        // No new frame, so the held key is still down, but the press was used
+        assert!(tick(&mut world) == [InputAction::Forward]);
+    }
+
+    #[test]
+    fn accelerate_independent_of_step() {
+        let target = Vec3::X * 3.0;
+        let one_step = accelerate(Vec3::ZERO, target, 12.0, 0.1);
+        let mut many_steps = Vec3::ZERO;
+        for _ in 0..10 {
+            many_steps = accelerate(many_steps, target, 12.0, 0.01);
+        }
+        assert!((one_step - many_steps).length() < 1e-4);
+    }
+
+    #[test]
+    fn ice_slides_further() {
+        let slide = |friction: f32| {
+            let mut velocity = Vec3::X * 3.0;
+            let mut dist = 0.0;
+            for _ in 0..60 {
+                velocity = accelerate(velocity, Vec3::ZERO, friction, 1.0 / 60.0);
+                dist += velocity.x / 60.0;
+            }
+            dist
+        };
+        let ice = slide(FloorTile::Ice.friction());
+        let sand = slide(FloorTile::Sand.friction());
+        assert!(ice > 1.0);
+        assert!(sand < 0.5);
+    }
+
+    #[test]
+    fn never_above_top_speed() {
+        let target = Vec3::Z * 2.0;
+        let mut velocity = Vec3::ZERO;
+        for _ in 0..1000 {
+            velocity = accelerate(velocity, target, 12.0, 0.5);
+            assert!(velocity.length() <= target.length() + 1e-5);
+        }
+        assert!((velocity - target).length() < 1e-4);
+    }
+}
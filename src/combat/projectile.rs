@@ -1,52 +1,94 @@
+use std::num::NonZeroU8;
+
 use bevy::prelude::*;
+use serde::Deserialize;
 
 use crate::{
-    physics::{Collider, MapCollisionEvent, PhysicsMovable},
+    map::MapData,
+    physics::{Collider, MapCollisionEvent, PhysicsMovable, SpatialIndex, WallHitEvent},
     render::{spritemap::SpriteSeq, RenderResource},
 };
 
-use super::{ai::AiMover, weapon::Weapon, CreatureStats, DamageEvent, DamageType, Team};
+use super::{
+    ai::{AiMover, AI},
+    boss::Boss,
+    monsterdefs::MonsterDefs,
+    status::{StatusEffects, StatusType},
+    weapon::Weapon,
+    CreatureStats, DamageEvent, DamageType, DeathEvent, KnockbackEvent, NoiseEvent, Relation, Team,
+};
+
+/// How far the sound of getting hurt travels, in tiles
+const HURT_LOUDNESS: f32 = 5.0;
+/// How far the sound of an explosion travels, in tiles
+const EXPLOSION_LOUDNESS: f32 = 10.0;
+/// Homing projectiles only look for targets within this distance
+const HOMING_RADIUS: f32 = 6.0;
 
-#[derive(Copy, Clone, PartialEq, Eq)]
-pub enum ProjectileType {
-    RedSpikes,
-    BlueBlob,
-    Shock,
-    Rock,
-    Fire,
-    Snowball,
+/// Area damage when a projectile hits something. The damage drops linearly to zero at the edge of the radius.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub struct Splash {
+    pub radius: f32,
 }
 
-impl ProjectileType {
-    pub fn speed(&self) -> f32 {
-        match self {
-            ProjectileType::BlueBlob => 8.0,
-            ProjectileType::RedSpikes => 6.0,
-            ProjectileType::Rock => 6.0,
-            ProjectileType::Fire => 6.0,
-            ProjectileType::Shock => 2.0,
-            ProjectileType::Snowball => 6.0,
+impl Splash {
+    pub fn damage_at(&self, damage: i16, dist: f32) -> i16 {
+        if dist >= self.radius {
+            return 0;
         }
+        (damage as f32 * (1.0 - dist / self.radius)).round() as i16
     }
+}
+
+/// How a projectile behaves after being fired. The behaviours can be combined freely.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProjectileBehaviour {
+    /// Number of times the projectile bounces off walls before it is destroyed
+    pub bounces: u8,
+    /// Number of creatures the projectile passes through before it is destroyed
+    pub pierce: u8,
+    /// Turn rate towards the nearest enemy in radians per second
+    pub homing: f32,
+    pub splash: Option<Splash>,
+}
+
+/// Refers to a projectile in `WeaponDefs`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProjectileType(pub(super) NonZeroU8);
 
+/// A projectile, as defined in `assets/data/weapons.ron`.
+#[derive(Deserialize)]
+pub struct ProjectileDef {
+    pub name: String,
+    pub sprite: String,
+    pub speed: f32,
+    pub radius: f32,
+    #[serde(default)]
+    pub sound: Option<String>,
+    #[serde(default)]
+    pub behaviour: ProjectileBehaviour,
+}
+
+impl ProjectileDef {
     pub fn make_uv(&self, tiles: &crate::render::spritemap::SpriteMap) -> SpriteSeq {
-        let str = match self {
-            ProjectileType::RedSpikes => "red_spikes.png",
-            ProjectileType::BlueBlob => "blue_blob.png",
-            ProjectileType::Shock => "shock.png",
-            ProjectileType::Rock => "rock.png",
-            ProjectileType::Fire => "fire.png",
-            ProjectileType::Snowball => "snowball.png",
-        };
-        tiles.get_projectile(str)
+        tiles.get_projectile(&self.sprite)
     }
 
     fn make_projectile(&self, team: Team, instigator: Entity, weapon: &Weapon) -> Projectile {
+        let behaviour = self.behaviour;
         Projectile {
             team,
             damage: weapon.damage,
             dam_type: weapon.dam_type,
+            knockback: weapon.knockback,
             instigator,
+            bounces_left: behaviour.bounces,
+            pierce_left: behaviour.pierce,
+            homing: behaviour.homing,
+            splash: behaviour.splash,
+            hits: vec![],
+            consumed: false,
         }
     }
 }
@@ -56,7 +98,35 @@ pub struct Projectile {
     pub team: Team,
     pub damage: i16,
     pub dam_type: DamageType,
+    pub knockback: bool,
     pub instigator: Entity,
+    pub bounces_left: u8,
+    pub pierce_left: u8,
+    pub homing: f32,
+    pub splash: Option<Splash>,
+    /// Creatures that have already been hit, so piercing projectiles only hit them once
+    hits: Vec<Entity>,
+    /// Set when the projectile is despawned after its last hit, so it can't also hit a wall in the same tick
+    consumed: bool,
+}
+
+impl Projectile {
+    /// Projectiles don't hit allies, the creature that fired them, or creatures they already hit.
+    fn can_hit(&self, target: Entity, target_team: Team, infighting: bool) -> bool {
+        self.team.can_hurt(target_team, infighting)
+            && target != self.instigator
+            && !self.hits.contains(&target)
+    }
+
+    fn map_collision_event(&self) -> MapCollisionEvent {
+        if self.bounces_left > 0 {
+            MapCollisionEvent::Bounce(1.0)
+        } else if self.splash.is_some() {
+            MapCollisionEvent::Stop // Destroyed by `projectile_hit_walls`, after exploding
+        } else {
+            MapCollisionEvent::Destroy
+        }
+    }
 }
 
 pub fn spawn_projectile(
@@ -65,81 +135,287 @@ pub fn spawn_projectile(
     pos: Vec3,
     dir: Vec3,
     weapon: &Weapon,
-    ptype: ProjectileType,
+    projectile_def: &ProjectileDef,
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     render_res: &mut ResMut<RenderResource>,
 ) {
-    let velocity = dir * ptype.speed();
+    let velocity = dir * projectile_def.speed;
 
-    let uv = ptype.make_uv(&render_res.sprites);
+    let uv = projectile_def.make_uv(&render_res.sprites);
 
     let mut proto_projectile = commands.spawn(uv.to_sprite_bundle(pos, meshes, render_res));
     proto_projectile.insert(crate::render::Animation::new(uv, 0.1));
-    proto_projectile.insert(ptype.make_projectile(team, instigator, weapon));
-    proto_projectile.insert(Collider::new(pos, 0.10)); // TODO: Electricity should have a higher radius.
-    proto_projectile.insert(PhysicsMovable::new(velocity, MapCollisionEvent::Destroy));
+    let projectile = projectile_def.make_projectile(team, instigator, weapon);
+    let on_hit_wall = projectile.map_collision_event();
+    proto_projectile.insert(projectile);
+    proto_projectile.insert(Collider::new(pos, projectile_def.radius));
+    proto_projectile.insert(PhysicsMovable::new(velocity, on_hit_wall));
 
     if weapon.range.is_finite() {
-        proto_projectile.insert(crate::lifecycle::Ttl::new(weapon.range / ptype.speed()));
+        proto_projectile.insert(crate::lifecycle::Ttl::new(
+            weapon.range / projectile_def.speed,
+        ));
     }
 }
 
+#[allow(clippy::too_many_arguments)] // Not really applicable for bevy systems
 pub fn check_collisions(
     mut commands: Commands,
-    mut projectile_query: Query<(Entity, &Projectile, &Collider)>,
-    mut target_query: Query<(Entity, &Collider, &CreatureStats)>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &Collider, &PhysicsMovable)>,
+    target_query: Query<(Entity, &Collider, &CreatureStats)>,
+    spatial_index: Res<SpatialIndex>,
+    map_data: Res<MapData>,
+    game_settings: Res<crate::GameSettings>,
     mut ev_damage: EventWriter<DamageEvent>,
+    mut ev_knockback: EventWriter<KnockbackEvent>,
+    mut ev_noise: EventWriter<NoiseEvent>,
 ) {
-    for (projectile_entity, projectile, projectile_body) in projectile_query.iter_mut() {
-        let projectile_body = projectile_body;
+    for (projectile_entity, mut projectile, projectile_body, movable) in &mut projectile_query {
+        if projectile.consumed {
+            continue;
+        }
 
-        for (target_entity, target_body, stats) in target_query.iter_mut() {
-            if projectile.team == stats.team {
-                continue;
-            }
+        // Sweep along the last physics step, so fast projectiles can't fly through creatures
+        let path = movable.last_move;
+        let start = projectile_body.with_pos(projectile_body.pos - path);
+        let reach = path.length() * 0.5 + projectile_body.radius;
 
-            let target_body = target_body;
-            if !projectile_body.collide_other(target_body) {
-                continue;
-            }
+        let mut hits: Vec<(f32, Entity)> = spatial_index
+            .nearby(start.pos + path * 0.5, reach)
+            .filter_map(|(entity, _)| {
+                let (target_entity, target_body, stats) = target_query.get(entity).ok()?;
+                if !projectile.can_hit(target_entity, stats.team, game_settings.infighting) {
+                    return None;
+                }
+                Some((start.sweep_other(path, target_body)?, target_entity))
+            })
+            .collect();
+        // Hit the creatures in the order the projectile reached them
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
 
+        for (t, target_entity) in hits {
             ev_damage.send(DamageEvent {
                 instigator: Some(projectile.instigator),
                 target: target_entity,
                 damage: projectile.damage,
                 dam_type: projectile.dam_type,
             });
+            projectile.hits.push(target_entity);
+            if projectile.knockback {
+                ev_knockback.send(KnockbackEvent {
+                    target: target_entity,
+                    dir: movable.velocity,
+                });
+            }
+
+            if projectile.splash.is_some() {
+                explode(
+                    &projectile,
+                    start.pos + path * t,
+                    &target_query,
+                    &spatial_index,
+                    &map_data,
+                    game_settings.infighting,
+                    &mut ev_damage,
+                    &mut ev_noise,
+                );
+            }
+
+            if projectile.pierce_left == 0 {
+                projectile.consumed = true;
+                commands.entity(projectile_entity).despawn();
+                break;
+            }
+            projectile.pierce_left -= 1;
+        }
+    }
+}
+
+/// Deals splash damage to all enemies around `pos`, except the ones that were hit directly.
+#[allow(clippy::too_many_arguments)]
+fn explode(
+    projectile: &Projectile,
+    pos: Vec3,
+    target_query: &Query<(Entity, &Collider, &CreatureStats)>,
+    spatial_index: &SpatialIndex,
+    map_data: &MapData,
+    infighting: bool,
+    ev_damage: &mut EventWriter<DamageEvent>,
+    ev_noise: &mut EventWriter<NoiseEvent>,
+) {
+    let Some(splash) = projectile.splash else {return;};
+
+    for (entity, _) in spatial_index.nearby(pos, splash.radius) {
+        let Ok((target_entity, target_body, stats)) = target_query.get(entity) else {continue;};
+        if !projectile.can_hit(target_entity, stats.team, infighting) {
+            continue;
+        }
+
+        let dist = (target_body.pos.distance(pos) - target_body.radius).max(0.0);
+        let damage = splash.damage_at(projectile.damage, dist);
+        if damage <= 0 || !map_data.line_of_sight(pos, target_body.pos) {
+            continue;
+        }
+
+        ev_damage.send(DamageEvent {
+            instigator: Some(projectile.instigator),
+            target: target_entity,
+            damage,
+            dam_type: projectile.dam_type,
+        });
+    }
+
+    ev_noise.send(NoiseEvent {
+        pos,
+        loudness: EXPLOSION_LOUDNESS,
+    });
+}
+
+#[allow(clippy::too_many_arguments)] // Not really applicable for bevy systems
+pub fn projectile_hit_walls(
+    mut commands: Commands,
+    mut ev_wall_hit: EventReader<WallHitEvent>,
+    mut projectile_query: Query<(&mut Projectile, &mut PhysicsMovable)>,
+    target_query: Query<(Entity, &Collider, &CreatureStats)>,
+    spatial_index: Res<SpatialIndex>,
+    map_data: Res<MapData>,
+    game_settings: Res<crate::GameSettings>,
+    mut ev_damage: EventWriter<DamageEvent>,
+    mut ev_noise: EventWriter<NoiseEvent>,
+) {
+    for ev in ev_wall_hit.read() {
+        let Ok((mut projectile, mut movable)) = projectile_query.get_mut(ev.entity) else {continue;};
+        if projectile.consumed || movable.on_hit_wall == MapCollisionEvent::Destroy {
+            continue; // Already despawned after hitting a creature, or by the physics
+        }
+
+        if projectile.bounces_left > 0 {
+            projectile.bounces_left -= 1;
+            movable.on_hit_wall = projectile.map_collision_event();
+            continue;
+        }
+
+        explode(
+            &projectile,
+            ev.pos,
+            &target_query,
+            &spatial_index,
+            &map_data,
+            game_settings.infighting,
+            &mut ev_damage,
+            &mut ev_noise,
+        );
+        projectile.consumed = true;
+        commands.entity(ev.entity).despawn();
+    }
+}
 
-            commands.entity(projectile_entity).despawn();
+/// Steers homing projectiles towards the nearest visible enemy, without changing their speed.
+pub fn home_projectiles(
+    time: Res<Time>,
+    mut projectile_query: Query<(&Projectile, &Collider, &mut PhysicsMovable)>,
+    target_query: Query<(&Collider, &CreatureStats), Without<Projectile>>,
+    spatial_index: Res<SpatialIndex>,
+    map_data: Res<MapData>,
+) {
+    for (projectile, projectile_body, mut movable) in projectile_query.iter_mut() {
+        if projectile.homing <= 0.0 {
+            continue;
         }
+
+        let pos = projectile_body.pos;
+        let target = spatial_index
+            .nearby(pos, HOMING_RADIUS)
+            .filter_map(|(entity, _)| target_query.get(entity).ok())
+            .filter(|(_, stats)| {
+                projectile.team.relation(stats.team) == Relation::Hostile && stats.alive()
+            })
+            .map(|(body, _)| body.pos)
+            .filter(|target| target.distance_squared(pos) < HOMING_RADIUS * HOMING_RADIUS)
+            .filter(|target| map_data.line_of_sight(pos, *target))
+            .min_by(|a, b| a.distance_squared(pos).total_cmp(&b.distance_squared(pos)));
+
+        let Some(target) = target else {continue;};
+        movable.velocity = steer(
+            movable.velocity,
+            target - pos,
+            projectile.homing * time.delta_seconds(),
+        );
     }
 }
 
+/// Rotates `velocity` around the Y axis towards `to`, by at most `max_angle` radians.
+fn steer(velocity: Vec3, to: Vec3, max_angle: f32) -> Vec3 {
+    let from_angle = f32::atan2(velocity.x, velocity.z);
+    let to_angle = f32::atan2(to.x, to.z);
+
+    let tau = std::f32::consts::TAU;
+    let delta = (to_angle - from_angle + tau * 1.5).rem_euclid(tau) - tau * 0.5;
+    Quat::from_rotation_y(delta.clamp(-max_angle, max_angle)) * velocity
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)] // Not really applicable for bevy systems
 pub fn take_damage_system(
     mut commands: Commands,
-    mut target_query: Query<(&mut CreatureStats, Option<&mut AiMover>)>,
+    mut target_query: Query<(
+        &mut CreatureStats,
+        &Collider,
+        Option<&mut AiMover>,
+        Option<&mut StatusEffects>,
+        Option<&Boss>,
+        Option<&mut AI>,
+    )>,
     mut game: ResMut<crate::GameInfo>,
+    monster_defs: Res<MonsterDefs>,
     mut game_state: ResMut<NextState<crate::game::GameState>>,
     mut map_data: ResMut<crate::map::MapData>,
     asset_server: Res<AssetServer>,
     mut ev_damage: EventReader<DamageEvent>,
     mut menu_info: ResMut<crate::ui::menus::MenuInfo>,
+    mut ev_noise: EventWriter<NoiseEvent>,
+    mut ev_death: EventWriter<DeathEvent>,
 ) {
     for ev in ev_damage.read() {
-        let Ok((mut stats, mut ai_pos)) =
+        let instigator_team = ev
+            .instigator
+            .and_then(|instigator| target_query.get(instigator).ok())
+            .map(|(stats, ..)| stats.team);
+
+        let Ok((mut stats, collider, mut ai_pos, effects, boss, ai)) =
             target_query.get_mut(ev.target) else {continue;};
 
+        // Its components are only removed at the end of the tick, so later hits can still find a killed creature
+        if !stats.alive() || boss.is_some_and(|boss| boss.shielded) {
+            continue;
+        }
+
         let hurt = stats.take_damage(
             ev,
             &mut commands,
             &mut game,
+            &monster_defs,
             &mut game_state,
             &mut map_data,
             &mut menu_info,
             ai_pos.as_deref_mut(),
         );
 
+        if !stats.alive() {
+            if let Some(monster_type) = stats.monster_type {
+                ev_death.send(DeathEvent {
+                    pos: collider.pos,
+                    monster_type,
+                });
+            }
+        }
+
+        if let (Some(mut ai), Some(instigator)) = (ai, ev.instigator) {
+            if instigator != ev.target && instigator_team == Some(stats.team) {
+                ai.retaliate(instigator);
+            }
+        }
+
         if hurt {
             if let Some(source) = stats.get_hurt_sound(&asset_server) {
                 commands.spawn(AudioBundle {
@@ -147,6 +423,46 @@ pub fn take_damage_system(
                     settings: default(),
                 });
             }
+            ev_noise.send(NoiseEvent {
+                pos: collider.pos,
+                loudness: HURT_LOUDNESS,
+            });
+
+            let status = StatusType::from_damage(ev.dam_type);
+            if let (Some(mut effects), Some(status)) = (effects, status) {
+                if ev.instigator.is_some() {
+                    effects.apply(status);
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splash_falloff() {
+        let splash = Splash { radius: 2.0 };
+        assert_eq!(splash.damage_at(10, 0.0), 10);
+        assert_eq!(splash.damage_at(10, 1.0), 5);
+        assert_eq!(splash.damage_at(10, 2.0), 0);
+        assert_eq!(splash.damage_at(10, 3.0), 0);
+    }
+
+    #[test]
+    fn steer_limits_turn_rate() {
+        let velocity = Vec3::new(0.0, 0.0, 2.0);
+
+        let turned = steer(velocity, Vec3::X, 0.1);
+        assert!((turned.length() - 2.0).abs() < 1e-5);
+        assert!((f32::atan2(turned.x, turned.z) - 0.1).abs() < 1e-5);
+
+        let turned = steer(velocity, Vec3::new(-1.0, 0.0, 0.0), 0.1);
+        assert!((f32::atan2(turned.x, turned.z) + 0.1).abs() < 1e-5);
+
+        let turned = steer(velocity, Vec3::new(0.01, 0.0, 1.0), 0.1);
+        assert!((turned.normalize() - Vec3::new(0.01, 0.0, 1.0).normalize()).length() < 1e-5);
+    }
+}
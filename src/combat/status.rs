@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use tinyvec::ArrayVec;
+
+use super::{CreatureStats, DamageEvent, DamageType};
+use crate::render::{RenderResource, Sprite3d, Tint};
+
+const BURN_DURATION: f32 = 3.0;
+const BURN_TICK: f32 = 0.5;
+const BURN_MAX_STACKS: u8 = 3;
+const CHILL_DURATION: f32 = 2.0;
+const CHILL_SPEED_FACTOR: f32 = 0.5;
+const STUN_DURATION: f32 = 0.4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum StatusType {
+    #[default]
+    Burning,
+    Chilled,
+    Stunned,
+}
+
+impl StatusType {
+    pub fn from_damage(dam_type: DamageType) -> Option<Self> {
+        match dam_type {
+            DamageType::Normal => None,
+            DamageType::Fire => Some(StatusType::Burning),
+            DamageType::Electric => Some(StatusType::Stunned),
+            DamageType::Cold => Some(StatusType::Chilled),
+        }
+    }
+
+    fn duration(&self) -> f32 {
+        match self {
+            StatusType::Burning => BURN_DURATION,
+            StatusType::Chilled => CHILL_DURATION,
+            StatusType::Stunned => STUN_DURATION,
+        }
+    }
+
+    fn tint(&self) -> Tint {
+        match self {
+            StatusType::Burning => Tint::Red,
+            StatusType::Chilled => Tint::Blue,
+            StatusType::Stunned => Tint::Yellow,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct StatusEffect {
+    status: StatusType,
+    timer: Timer,
+    /// Burning stacks increase the damage per tick. Other effects always have one stack.
+    stacks: u8,
+}
+
+/// Timed effects on a creature, caused by the damage type of hits it took.
+/// - Burning stacks up to three times and refreshes its duration when hit again.
+/// - Chilled refreshes its duration, but doesn't stack.
+/// - Stunned can't be refreshed while active, so monsters can't be stunned forever.
+#[derive(Component, Default)]
+pub struct StatusEffects {
+    effects: ArrayVec<[StatusEffect; 3]>,
+    burn_timer: Timer,
+}
+
+impl StatusEffects {
+    pub fn apply(&mut self, status: StatusType) {
+        let duration = Duration::from_secs_f32(status.duration());
+
+        if let Some(effect) = self.effects.iter_mut().find(|e| e.status == status) {
+            match status {
+                StatusType::Burning => {
+                    effect.stacks = u8::min(effect.stacks + 1, BURN_MAX_STACKS);
+                    effect.timer.set_duration(duration);
+                    effect.timer.reset();
+                }
+                StatusType::Chilled => effect.timer.reset(),
+                StatusType::Stunned => {}
+            }
+            return;
+        }
+
+        if status == StatusType::Burning {
+            self.burn_timer = Timer::from_seconds(BURN_TICK, TimerMode::Repeating);
+        }
+        self.effects.push(StatusEffect {
+            status,
+            timer: Timer::new(duration, TimerMode::Once),
+            stacks: 1,
+        });
+    }
+
+    /// Advances all effects and returns the burn damage dealt during this tick.
+    pub fn tick(&mut self, delta: Duration) -> i16 {
+        let mut burn_damage = 0;
+        if let Some(stacks) = self.get(StatusType::Burning).map(|e| e.stacks) {
+            let ticks = self.burn_timer.tick(delta).times_finished_this_tick() as i16;
+            burn_damage = ticks * stacks as i16;
+        }
+
+        for effect in self.effects.iter_mut() {
+            effect.timer.tick(delta);
+        }
+        self.effects.retain(|e| !e.timer.finished());
+
+        burn_damage
+    }
+
+    fn get(&self, status: StatusType) -> Option<&StatusEffect> {
+        self.effects.iter().find(|e| e.status == status)
+    }
+
+    pub fn has(&self, status: StatusType) -> bool {
+        self.get(status).is_some()
+    }
+
+    pub fn is_stunned(&self) -> bool {
+        self.has(StatusType::Stunned)
+    }
+
+    /// Multiplier for the movement speed of the creature.
+    pub fn speed_factor(&self) -> f32 {
+        if self.has(StatusType::Chilled) {
+            CHILL_SPEED_FACTOR
+        } else {
+            1.0
+        }
+    }
+
+    /// The most recently applied effect determines the color.
+    pub fn tint(&self) -> Tint {
+        self.effects
+            .last()
+            .map_or(Tint::None, |effect| effect.status.tint())
+    }
+}
+
+pub fn tick_status_effects(
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut StatusEffects, &CreatureStats)>,
+    mut ev_damage: EventWriter<DamageEvent>,
+) {
+    for (entity, mut effects, stats) in query.iter_mut() {
+        let burn_damage = effects.tick(time.delta());
+        if burn_damage > 0 && stats.alive() {
+            ev_damage.send(DamageEvent {
+                instigator: None,
+                target: entity,
+                damage: burn_damage,
+                dam_type: DamageType::Fire,
+            });
+        }
+    }
+}
+
+pub fn update_status_tint(
+    mut query: Query<(&StatusEffects, &mut Sprite3d, &mut Handle<Mesh>)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut render_res: ResMut<RenderResource>,
+) {
+    for (effects, mut sprite, mut mesh) in query.iter_mut() {
+        let tint = effects.tint();
+        if sprite.tint != tint {
+            sprite.tint = tint;
+            *mesh = render_res.get_mesh(*sprite, &mut meshes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f32) -> Duration {
+        Duration::from_secs_f32(secs)
+    }
+
+    #[test]
+    fn burning_stacks() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusType::Burning);
+        assert_eq!(effects.tick(secs(0.5)), 1);
+
+        effects.apply(StatusType::Burning);
+        effects.apply(StatusType::Burning);
+        effects.apply(StatusType::Burning);
+        assert_eq!(effects.tick(secs(0.5)), 3);
+
+        // Refreshed by the last hit
+        assert_eq!(effects.tick(secs(2.0)), 12);
+        assert!(effects.has(StatusType::Burning));
+        effects.tick(secs(1.0));
+        assert!(!effects.has(StatusType::Burning));
+    }
+
+    #[test]
+    fn chill_refreshes() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusType::Chilled);
+        assert_eq!(effects.speed_factor(), CHILL_SPEED_FACTOR);
+
+        effects.tick(secs(1.5));
+        effects.apply(StatusType::Chilled);
+        effects.tick(secs(1.5));
+        assert!(effects.has(StatusType::Chilled));
+
+        effects.tick(secs(1.0));
+        assert_eq!(effects.speed_factor(), 1.0);
+    }
+
+    #[test]
+    fn stun_does_not_refresh() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusType::Stunned);
+        effects.tick(secs(0.3));
+        effects.apply(StatusType::Stunned);
+        assert!(effects.is_stunned());
+
+        effects.tick(secs(0.2));
+        assert!(!effects.is_stunned());
+    }
+}
@@ -1,132 +1,178 @@
+use std::num::NonZeroU8;
+
 use bevy::prelude::*;
+use serde::Deserialize;
 
 use super::{
     ai::AI,
+    arsenal::Arsenal,
+    hitscan,
     projectile::{spawn_projectile, ProjectileType},
-    CreatureStats, DamageEvent, DamageType,
+    status::StatusEffects,
+    weapondefs::WeaponDefs,
+    CombatRng, CreatureStats, DamageEvent, DamageType, KnockbackEvent, NoiseEvent, Relation,
+};
+use crate::{
+    map::MapData,
+    physics::{Collider, SpatialIndex},
 };
 
 #[derive(Component)]
 pub struct Weapon {
+    pub weapon_type: WeaponType,
     firing: bool,
-    cooldown: Timer,
+    pub(super) cooldown: Timer,
     pub effect: WeaponEffect,
     pub damage: i16,
     pub dam_type: DamageType,
+    pub knockback: bool,
 
     /// For melee, this is the reach, for range weapons, it is the max distance that projectiles can fly
     pub range: f32,
+
+    /// Weapons without an ammo type can fire forever
+    pub ammo_type: Option<AmmoType>,
 }
 
-pub enum WeaponEffect {
-    Ranged {
-        ptype: ProjectileType,
-        accuracy: f32,
-    },
-    RangedArc {
-        ptype: ProjectileType,
-        arc: f32,
-        count: u8,
-    },
-    Melee {
-        arc: f32,
-    },
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum AmmoType {
+    Fire,
+    Shock,
+    Snow,
 }
 
-impl Weapon {
-    pub fn set_fire_state(&mut self, firing: bool) {
-        self.firing = firing;
+impl AmmoType {
+    pub const ALL: [AmmoType; 3] = [AmmoType::Fire, AmmoType::Shock, AmmoType::Snow];
 
-        if firing && self.cooldown.paused() {
-            self.cooldown.unpause();
+    pub const fn max(&self) -> i16 {
+        match self {
+            AmmoType::Fire => 40,
+            AmmoType::Shock => 30,
+            AmmoType::Snow => 100,
         }
     }
 
-    pub fn new(
-        fire_speed: f32,
-        damage: i16,
-        dam_type: DamageType,
-        range: f32,
-        effect: WeaponEffect,
-    ) -> Self {
-        Self {
-            cooldown: Timer::from_seconds(fire_speed, TimerMode::Once),
-            firing: true,
-            effect,
-            damage,
-            range,
-            dam_type,
+    pub const fn pickup_amount(&self) -> i16 {
+        match self {
+            AmmoType::Fire => 10,
+            AmmoType::Shock => 8,
+            AmmoType::Snow => 25,
         }
     }
+}
 
-    pub fn new_melee(fire_speed: f32, damage: i16, dam_type: DamageType) -> Self {
-        Self::new(
-            fire_speed,
-            damage,
-            dam_type,
-            1.5, // TODO
-            WeaponEffect::Melee {
-                arc: 1.0,   // TODO
-            },
-        )
-    }
+/// Projectiles are referred to by `ProjectileType`, or by name in the data files.
+/// Hitscan weapons hit instantly and only use their projectile for the impact sprite.
+#[derive(Clone, Deserialize)]
+pub enum WeaponEffect<P = ProjectileType> {
+    Ranged { ptype: P, accuracy: f32 },
+    RangedArc { ptype: P, arc: f32, count: u8 },
+    Melee { arc: f32 },
+    Hitscan { impact: P },
+}
 
-    pub fn new_ranged(
-        fire_speed: f32,
-        projectile: ProjectileType,
-        range: f32,
-        damage: i16,
-        dam_type: DamageType,
-    ) -> Self {
-        Self::new(
-            fire_speed,
-            damage,
-            dam_type,
-            range,
-            WeaponEffect::Ranged {
-                ptype: projectile,
-                accuracy: 0.0,
-            },
-        )
+/// Refers to a weapon in `WeaponDefs`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WeaponType(pub(super) NonZeroU8);
+
+/// A weapon, as defined in `assets/data/weapons.ron`.
+#[derive(Deserialize)]
+pub struct WeaponDef<P = ProjectileType> {
+    pub name: String,
+    pub title: String,
+    pub cooldown: f32,
+    pub damage: i16,
+    pub dam_type: DamageType,
+    /// Weapons without a range can fire at any distance
+    #[serde(default)]
+    pub range: Option<f32>,
+    pub effect: WeaponEffect<P>,
+    #[serde(default)]
+    pub ammo: Option<AmmoType>,
+    /// Heavy weapons push the monsters they hit into the next tile
+    #[serde(default)]
+    pub knockback: bool,
+    /// Ranged weapons use the sound of their projectile when this is `None`
+    #[serde(default)]
+    pub sound: Option<String>,
+}
+
+impl WeaponDef {
+    pub fn make_weapon(&self, weapon_type: WeaponType) -> Weapon {
+        Weapon {
+            weapon_type,
+            cooldown: Timer::from_seconds(self.cooldown, TimerMode::Once),
+            firing: true,
+            effect: self.effect.clone(),
+            damage: self.damage,
+            range: self.range.unwrap_or(f32::INFINITY),
+            dam_type: self.dam_type,
+            knockback: self.knockback,
+            ammo_type: self.ammo,
+        }
     }
+}
 
-    pub fn get_sound(&self) -> &'static str {
-        fn get_projectile_sound(ptype: ProjectileType) -> &'static str {
-            match ptype {
-                ProjectileType::RedSpikes => "audio/shoot_redspikes.ogg",
-                ProjectileType::Fire => "audio/shoot_fire.ogg",
-                ProjectileType::Rock => "audio/shoot_rock.ogg",
-                ProjectileType::BlueBlob => "audio/shoot_blueblob.ogg",
-                ProjectileType::Shock => "audio/shoot_shock.ogg",
-                ProjectileType::Snowball => "audio/shoot_snow.ogg",
-            }
+impl Weapon {
+    pub fn set_fire_state(&mut self, firing: bool) {
+        self.firing = firing;
+
+        if firing && self.cooldown.paused() {
+            self.cooldown.unpause();
         }
+    }
 
+    /// How far the sound of this weapon travels, in tiles
+    pub fn loudness(&self) -> f32 {
         match self.effect {
-            WeaponEffect::Ranged { ptype, .. } => get_projectile_sound(ptype),
-            WeaponEffect::RangedArc { ptype, .. } => get_projectile_sound(ptype),
-            WeaponEffect::Melee { .. } => "audio/melee.ogg",
+            WeaponEffect::Ranged { .. } => 8.0,
+            WeaponEffect::RangedArc { .. } => 10.0,
+            WeaponEffect::Melee { .. } => 3.0,
+            WeaponEffect::Hitscan { .. } => 8.0,
         }
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)] // Not really applicable for bevy systems
 pub fn fire_weapons(
     mut commands: Commands,
     time: Res<Time>,
-    mut query: Query<(Entity, &mut Weapon, &CreatureStats, &Transform, Option<&AI>)>,
+    mut rng: ResMut<CombatRng>,
+    mut query: Query<(
+        Entity,
+        &mut Weapon,
+        &CreatureStats,
+        &Transform,
+        Option<&AI>,
+        Option<&StatusEffects>,
+        Option<&mut Arsenal>,
+    )>,
     melee_target_query: Query<(Entity, &CreatureStats, &Transform)>,
+    hitscan_target_query: Query<(Entity, &CreatureStats, &Collider)>,
+    map_data: Res<MapData>,
+    spatial_index: Res<SpatialIndex>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut render_res: ResMut<crate::render::RenderResource>,
+    weapon_defs: Res<WeaponDefs>,
+    game_settings: Res<crate::GameSettings>,
     asset_server: Res<AssetServer>,
     mut ev_damage: EventWriter<DamageEvent>,
+    mut ev_knockback: EventWriter<KnockbackEvent>,
+    mut ev_noise: EventWriter<NoiseEvent>,
 ) {
-    for (instigator, mut weapon, stats, transform, ai) in query.iter_mut() {
+    for (instigator, mut weapon, stats, transform, ai, effects, mut arsenal) in query.iter_mut() {
+        if ai.is_some() && effects.is_some_and(|e| e.is_stunned()) {
+            continue; // Stunned monsters can't attack and their cooldown is paused
+        }
         if !weapon.cooldown.tick(time.delta()).finished() {
             continue;
         }
         if !weapon.firing {
             continue;
         }
+        if arsenal.as_ref().is_some_and(|a| !a.has_ammo(&weapon)) {
+            continue;
+        }
 
         let pos = transform.translation;
         let dir = match ai {
@@ -142,17 +188,21 @@ pub fn fire_weapons(
         match weapon.effect {
             WeaponEffect::Melee { arc } => {
                 let mut ai_hits = false;
-                for (target, target_stats, target_transform) in melee_target_query.iter() {
-                    if stats.team == target_stats.team {
+                for (entity, _) in spatial_index.nearby(pos, weapon.range) {
+                    let Ok((target, target_stats, target_transform)) = melee_target_query.get(entity) else {continue;};
+                    // Swings only hit enemies, and the creature a monster holds a grudge against
+                    let enemy = stats.team.relation(target_stats.team) == Relation::Hostile
+                        || ai.is_some_and(|ai| ai.grudge() == Some(target));
+                    if target == instigator || !enemy {
                         continue;
                     }
 
-                    let delta = pos - target_transform.translation;
+                    let delta = target_transform.translation - pos;
 
                     if delta.length_squared() > weapon.range * weapon.range {
                         continue;
                     }
-                    if -delta.angle_between(dir) > arc / 2.0 {
+                    if delta.angle_between(dir) > arc / 2.0 {
                         continue;
                     }
 
@@ -162,6 +212,9 @@ pub fn fire_weapons(
                         damage: weapon.damage,
                         dam_type: weapon.dam_type,
                     });
+                    if weapon.knockback {
+                        ev_knockback.send(KnockbackEvent { target, dir: delta });
+                    }
                     ai_hits = true;
                 }
 
@@ -180,15 +233,46 @@ pub fn fire_weapons(
                         pos,
                         dir,
                         &weapon,
-                        ptype,
+                        weapon_defs.projectile(ptype),
                         &mut commands,
                         &mut meshes,
                         &mut render_res,
                     );
                 }
             }
+            WeaponEffect::Hitscan { impact } => {
+                let infighting = game_settings.infighting;
+                let targets = hitscan_target_query
+                    .iter()
+                    .filter(|(target, target_stats, _)| {
+                        *target != instigator && stats.team.can_hurt(target_stats.team, infighting)
+                    })
+                    .map(|(target, _, collider)| (target, collider));
+                let hit = hitscan::trace(&map_data, pos, dir, weapon.range, targets);
+
+                if let Some(target) = hit.target {
+                    ev_damage.send(DamageEvent {
+                        instigator: Some(instigator),
+                        target,
+                        damage: weapon.damage,
+                        dam_type: weapon.dam_type,
+                    });
+                    if weapon.knockback {
+                        ev_knockback.send(KnockbackEvent { target, dir });
+                    }
+                }
+
+                // Pull the impact back a little, so it isn't hidden in the wall
+                hitscan::spawn_impact(
+                    hit.pos - dir * 0.1,
+                    weapon_defs.projectile(impact),
+                    &mut commands,
+                    &mut meshes,
+                    &mut render_res,
+                );
+            }
             WeaponEffect::Ranged { ptype, accuracy } => {
-                let dir = Quat::from_rotation_y((fastrand::f32() - 0.5) * accuracy) * dir;
+                let dir = Quat::from_rotation_y((rng.0.f32() - 0.5) * accuracy) * dir;
 
                 spawn_projectile(
                     instigator,
@@ -196,7 +280,7 @@ pub fn fire_weapons(
                     pos,
                     dir,
                     &weapon,
-                    ptype,
+                    weapon_defs.projectile(ptype),
                     &mut commands,
                     &mut meshes,
                     &mut render_res,
@@ -205,9 +289,18 @@ pub fn fire_weapons(
         };
 
         weapon.cooldown.reset();
-        commands.spawn(AudioBundle {
-            source: asset_server.load(weapon.get_sound()),
-            settings: default(),
+        if let Some(arsenal) = arsenal.as_mut() {
+            arsenal.use_ammo(&weapon);
+        }
+        if let Some(sound) = &weapon_defs.get(weapon.weapon_type).sound {
+            commands.spawn(AudioBundle {
+                source: asset_server.load(sound),
+                settings: default(),
+            });
+        }
+        ev_noise.send(NoiseEvent {
+            pos,
+            loudness: weapon.loudness(),
         });
     }
 }
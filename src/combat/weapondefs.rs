@@ -0,0 +1,265 @@
+use std::{num::NonZeroU8, path::Path};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{
+    defs::{self, DefError},
+    projectile::{ProjectileDef, ProjectileType},
+    weapon::{Weapon, WeaponDef, WeaponEffect, WeaponType},
+};
+
+const WEAPON_DEFS_PATH: &str = "./assets/data/weapons.ron";
+const ASSET_DIR: &str = "./assets";
+
+#[derive(Deserialize)]
+struct WeaponDefsFile {
+    projectiles: Vec<ProjectileDef>,
+    weapons: Vec<WeaponDef<String>>,
+    loadout: Vec<String>,
+}
+
+/// All weapons and projectiles, indexed by `WeaponType` and `ProjectileType`.
+#[derive(Resource, Default)]
+pub struct WeaponDefs {
+    projectiles: Vec<ProjectileDef>,
+    weapons: Vec<WeaponDef>,
+    loadout: Vec<WeaponType>,
+}
+
+impl WeaponDefs {
+    pub fn load() -> Result<Self, DefError> {
+        defs::load(WEAPON_DEFS_PATH, |text| {
+            Self::parse(text, |path| Path::new(ASSET_DIR).join(path).is_file())
+        })
+    }
+
+    /// Parses and validates weapon definitions. `asset_exists` checks whether a path within the assets folder exists.
+    pub fn parse(text: &str, asset_exists: impl Fn(&str) -> bool) -> Result<Self, DefError> {
+        let file: WeaponDefsFile = defs::from_ron(text)?;
+
+        if file.projectiles.len() >= u8::MAX as usize || file.weapons.len() >= u8::MAX as usize {
+            return Err(DefError::TooManyDefs);
+        }
+        defs::check_unique(file.projectiles.iter().map(|p| p.name.as_str()))?;
+        defs::check_unique(file.weapons.iter().map(|w| w.name.as_str()))?;
+
+        let check_asset = |name: &str, path: &str| {
+            if asset_exists(path) {
+                Ok(())
+            } else {
+                Err(DefError::Unknown {
+                    def: name.to_string(),
+                    kind: "asset",
+                    name: path.to_string(),
+                })
+            }
+        };
+
+        let mut defs = Self {
+            projectiles: file.projectiles,
+            weapons: vec![],
+            loadout: vec![],
+        };
+
+        for projectile in &defs.projectiles {
+            check_asset(
+                &projectile.name,
+                &format!("projectiles/{}", projectile.sprite),
+            )?;
+            if let Some(sound) = &projectile.sound {
+                check_asset(&projectile.name, sound)?;
+            }
+        }
+
+        for weapon in file.weapons {
+            let weapon = defs.resolve(weapon)?;
+            if let Some(sound) = &weapon.sound {
+                check_asset(&weapon.name, sound)?;
+            }
+            defs.weapons.push(weapon);
+        }
+
+        for name in file.loadout {
+            let weapon_type = defs.find(&name).ok_or(DefError::Unknown {
+                def: "loadout".to_string(),
+                kind: "weapon",
+                name,
+            })?;
+            defs.loadout.push(weapon_type);
+        }
+        if defs.loadout.is_empty() {
+            return Err(DefError::EmptyLoadout);
+        }
+
+        Ok(defs)
+    }
+
+    /// Replaces the projectile names by their types and gives ranged weapons the sound of their projectile.
+    fn resolve(&self, weapon: WeaponDef<String>) -> Result<WeaponDef, DefError> {
+        let find_projectile = |name: &String| {
+            self.find_projectile(name).ok_or_else(|| DefError::Unknown {
+                def: weapon.name.clone(),
+                kind: "projectile",
+                name: name.clone(),
+            })
+        };
+
+        let effect = match &weapon.effect {
+            WeaponEffect::Ranged { ptype, accuracy } => WeaponEffect::Ranged {
+                ptype: find_projectile(ptype)?,
+                accuracy: *accuracy,
+            },
+            WeaponEffect::RangedArc { ptype, arc, count } => WeaponEffect::RangedArc {
+                ptype: find_projectile(ptype)?,
+                arc: *arc,
+                count: *count,
+            },
+            WeaponEffect::Melee { arc } => WeaponEffect::Melee { arc: *arc },
+            WeaponEffect::Hitscan { impact } => WeaponEffect::Hitscan {
+                impact: find_projectile(impact)?,
+            },
+        };
+
+        let sound = weapon.sound.or_else(|| match effect {
+            WeaponEffect::Ranged { ptype, .. }
+            | WeaponEffect::RangedArc { ptype, .. }
+            | WeaponEffect::Hitscan { impact: ptype } => self.projectile(ptype).sound.clone(),
+            WeaponEffect::Melee { .. } => None,
+        });
+
+        Ok(WeaponDef {
+            name: weapon.name,
+            title: weapon.title,
+            cooldown: weapon.cooldown,
+            damage: weapon.damage,
+            dam_type: weapon.dam_type,
+            range: weapon.range,
+            effect,
+            ammo: weapon.ammo,
+            knockback: weapon.knockback,
+            sound,
+        })
+    }
+
+    pub fn find(&self, name: &str) -> Option<WeaponType> {
+        let index = self.weapons.iter().position(|def| def.name == name)?;
+        Some(WeaponType(NonZeroU8::new(index as u8 + 1).unwrap()))
+    }
+
+    pub fn find_projectile(&self, name: &str) -> Option<ProjectileType> {
+        let index = self.projectiles.iter().position(|def| def.name == name)?;
+        Some(ProjectileType(NonZeroU8::new(index as u8 + 1).unwrap()))
+    }
+
+    pub fn get(&self, weapon_type: WeaponType) -> &WeaponDef {
+        &self.weapons[weapon_type.0.get() as usize - 1]
+    }
+
+    pub fn projectile(&self, ptype: ProjectileType) -> &ProjectileDef {
+        &self.projectiles[ptype.0.get() as usize - 1]
+    }
+
+    pub fn make_weapon(&self, weapon_type: WeaponType) -> Weapon {
+        self.get(weapon_type).make_weapon(weapon_type)
+    }
+
+    /// The weapons the player starts with, in slot order.
+    pub fn loadout(&self) -> &[WeaponType] {
+        &self.loadout
+    }
+}
+
+pub fn load_weapon_defs(mut commands: Commands) {
+    match WeaponDefs::load() {
+        Ok(defs) => commands.insert_resource(defs),
+        Err(err) => panic!("Loading {} failed. {}", WEAPON_DEFS_PATH, err),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// The weapon definitions as shipped with the game.
+    pub fn weapon_defs() -> WeaponDefs {
+        WeaponDefs::load().expect("The shipped weapon definitions should be valid")
+    }
+
+    const PROJECTILES: &str = r#"projectiles: [
+        (name: "fire", sprite: "fire.png", speed: 6.0, radius: 0.1, sound: Some("audio/fire.ogg")),
+    ]"#;
+
+    fn parse(weapons: &str) -> Result<WeaponDefs, DefError> {
+        let text = format!(
+            "({}, weapons: [{}], loadout: [\"staff\"])",
+            PROJECTILES, weapons
+        );
+        WeaponDefs::parse(&text, |_| true)
+    }
+
+    const STAFF: &str = r#"(
+        name: "staff",
+        title: "Staff",
+        cooldown: 0.5,
+        damage: 10,
+        dam_type: Fire,
+        effect: Ranged(ptype: "fire", accuracy: 0.0),
+    )"#;
+
+    #[test]
+    fn shipped_defs() {
+        let defs = weapon_defs();
+        let wand = defs.find("wand").unwrap();
+        assert_eq!(defs.loadout()[0], wand);
+        assert_eq!(defs.get(wand).title, "Wand");
+        assert!(defs.find("demon_fire").is_some());
+    }
+
+    #[test]
+    fn projectile_sound() {
+        let defs = parse(STAFF).unwrap();
+        let staff = defs.get(defs.find("staff").unwrap());
+        assert_eq!(staff.sound.as_deref(), Some("audio/fire.ogg"));
+        assert_eq!(staff.range, None);
+
+        let defs =
+            parse(&STAFF.replace("effect:", "sound: Some(\"audio/staff.ogg\"), effect:")).unwrap();
+        let staff = defs.get(defs.find("staff").unwrap());
+        assert_eq!(staff.sound.as_deref(), Some("audio/staff.ogg"));
+    }
+
+    #[test]
+    fn unknown_projectile() {
+        let result = parse(&STAFF.replace("ptype: \"fire\"", "ptype: \"laser\""));
+        assert!(matches!(
+            result,
+            Err(DefError::Unknown {
+                kind: "projectile",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn unknown_loadout_weapon() {
+        let result = parse(&STAFF.replace("name: \"staff\"", "name: \"wand\""));
+        assert!(matches!(
+            result,
+            Err(DefError::Unknown { kind: "weapon", .. })
+        ));
+    }
+
+    #[test]
+    fn unknown_asset() {
+        let text = format!(
+            "({}, weapons: [{}], loadout: [\"staff\"])",
+            PROJECTILES, STAFF
+        );
+        let result = WeaponDefs::parse(&text, |path| path != "projectiles/fire.png");
+        assert!(matches!(
+            result,
+            Err(DefError::Unknown { kind: "asset", .. })
+        ));
+    }
+}
@@ -1,10 +1,17 @@
-use bevy::{prelude::*, window::CursorGrabMode};
+use bevy::{
+    ecs::schedule::{LogLevel, ScheduleBuildSettings},
+    prelude::*,
+    window::CursorGrabMode,
+};
 
 use crate::{
     combat::player::Player, lifecycle::LevelObject, map::MapData, mapgen::style::LevelStyle,
-    spawner::Spawner,
+    spawner::Spawner, spawnobject::SpawnObject,
 };
 
+/// Gameplay runs on a fixed timestep of this many ticks per second, so it plays out the same at any frame rate.
+pub const TICK_RATE: f64 = 64.0;
+
 #[derive(Default, Debug, Hash, PartialEq, Eq, Clone, Copy, States)]
 
 pub enum GameState {
@@ -14,6 +21,27 @@ pub enum GameState {
     GameMenu,
 }
 
+/// The systems that advance the game on the fixed timestep. Once a tick asks for another state, the ticks after it
+/// are skipped until that state is entered, so catching up on ticks never plays on behind a menu.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GameTick;
+
+/// The steps of a tick, in the order they run. The systems within a step run one after another as well, so the same
+/// input always plays out the same way.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TickSet {
+    /// Collects the actions of this tick, from the player or a replay
+    Input,
+    Player,
+    Ai,
+    Physics,
+    /// Firing weapons, projectiles and hazards, up to dealing the damage
+    Combat,
+    /// Reacts to the damage, like bosses changing phase and monsters dying
+    Aftermath,
+    Cleanup,
+}
+
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
@@ -22,26 +50,73 @@ impl Plugin for GamePlugin {
             .add_systems(OnEnter(GameState::InGame), (start_level, capture_mouse))
             .add_systems(OnExit(GameState::InGame), release_mouse)
             .add_event::<crate::interactable::TriggerEvent>()
+            .add_event::<crate::physics::WallHitEvent>()
             .insert_resource(crate::map::MapData::default())
             .insert_resource(crate::render::RenderResource::default())
             .insert_resource(crate::GameInfo::default())
             .insert_resource(crate::GameSettings::default())
-            .add_systems(
-                Update,
+            .insert_resource(crate::items::loot::LootRng::default())
+            .insert_resource(crate::physics::SpatialIndex::default())
+            .insert_resource(Time::<Fixed>::from_hz(TICK_RATE))
+            .edit_schedule(FixedUpdate, |schedule| {
+                // Systems that share data without an order between them would make replays play out differently
+                schedule.set_build_settings(ScheduleBuildSettings {
+                    ambiguity_detection: LogLevel::Warn,
+                    ..default()
+                });
+            })
+            .configure_sets(
+                FixedUpdate,
+                GameTick
+                    .run_if(in_state(GameState::InGame))
+                    .run_if(no_state_change),
+            )
+            .configure_sets(
+                FixedUpdate,
                 (
-                    crate::physics::do_physics.after(crate::combat::player::handle_player_move),
-                    crate::interactable::update_doors
-                        .after(crate::combat::player::handle_player_interactions),
-                    crate::items::pickup::check_pickups.after(crate::physics::do_physics),
-                    crate::render::face_camera.after(crate::physics::do_physics),
-                    crate::render::animate_sprites,
-                    crate::lifecycle::check_ttl,
+                    TickSet::Input,
+                    TickSet::Player,
+                    TickSet::Ai,
+                    TickSet::Physics,
+                    TickSet::Combat,
+                    TickSet::Aftermath,
+                    TickSet::Cleanup,
                 )
+                    .chain()
+                    .in_set(GameTick),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    (
+                        crate::interactable::update_doors,
+                        crate::physics::do_physics,
+                        crate::physics::push_out_of_creatures,
+                        crate::physics::update_spatial_index,
+                    )
+                        .chain()
+                        .in_set(TickSet::Physics),
+                    (
+                        crate::items::loot::drop_loot,
+                        crate::items::pickup::check_pickups,
+                        crate::lifecycle::check_ttl,
+                    )
+                        .chain()
+                        .in_set(TickSet::Cleanup),
+                ),
+            )
+            .add_systems(
+                Update,
+                (crate::render::face_camera, crate::render::animate_sprites)
                     .run_if(in_state(GameState::InGame)),
             );
     }
 }
 
+fn no_state_change(next_state: Res<NextState<GameState>>) -> bool {
+    next_state.0.is_none()
+}
+
 fn capture_mouse(mut windows: Query<&mut Window>) {
     for mut window in &mut windows {
         window.cursor.grab_mode = CursorGrabMode::Locked;
@@ -81,10 +156,14 @@ fn start_level(
     mut map_data: ResMut<MapData>,
     mut meshes: ResMut<Assets<Mesh>>,
     render_res: ResMut<crate::render::RenderResource>,
+    monster_defs: Res<crate::combat::monsterdefs::MonsterDefs>,
+    weapon_defs: Res<crate::combat::weapondefs::WeaponDefs>,
     mut level_query: Query<Entity, With<LevelObject>>,
     mut player_query: Query<&mut Transform, With<Player>>,
     game_settings: Res<crate::GameSettings>,
     cl_args: Res<crate::CommandLineArgs>,
+    mut loot_rng: ResMut<crate::items::loot::LootRng>,
+    mut combat_rng: ResMut<crate::combat::CombatRng>,
 ) {
     if game_data.level_spawned {
         return; // No need to spawn the level again
@@ -106,6 +185,8 @@ fn start_level(
 
     let level = game_data.level;
     println!("Seed: {}", rng.get_seed());
+    loot_rng.0.seed(rng.get_seed() ^ level as u64);
+    combat_rng.0.seed(!rng.get_seed() ^ level as u64);
 
     // Get initial data
     let map_gen_result = crate::mapgen::make_map(level, game_data.level_style, &mut rng);
@@ -122,6 +203,8 @@ fn start_level(
         monster_map: map_gen_result.tilemap.map(|t| t.is_solid()),
         player_pos,
         tile_map: map_gen_result.tilemap.clone(),
+        room_centres: map_gen_result.room_centres.clone(),
+        doors: map_gen_result.tilemap.map(|_| None),
     };
 
     // Spawn the map mesh
@@ -144,6 +227,7 @@ fn start_level(
         let player = commands
             .spawn(crate::combat::player::PlayerBundle::new(
                 map_gen_result.player_pos.to_vec(0.7),
+                &weapon_defs,
             ))
             .insert(PbrBundle {
                 transform: player_pos,
@@ -157,34 +241,49 @@ fn start_level(
 
     // Add the monsters
     let level_style = game_data.level_style;
+    game_data.boss_fight = map_gen_result
+        .spawn_objects
+        .iter()
+        .any(|(_, object)| *object == SpawnObject::Lich);
 
     let mut spawner = Spawner {
         map_data,
         commands,
         meshes,
         render_res,
+        monster_defs,
+        weapon_defs,
     };
 
     let monster_count = level * 3 + 12;
     for _ in 0..monster_count {
         use crate::mapgen::randitem::RandItem;
-        let monster_type = *level_style.monsters().rand_front_loaded(&mut rng);
+        let monster_type = *spawner
+            .monster_defs
+            .level_monsters(level_style)
+            .rand_front_loaded(&mut rng);
         spawner.try_spawn_monster(monster_type, &mut rng);
     }
 
-    // Add level portal or phylactery
+    // Add level portals or the boss and its phylactery
     for (pos, object_type) in map_gen_result.spawn_objects.iter() {
         spawner.spawn_object_at_pos(*pos, object_type, &mut rng);
     }
 
     // Add pickups
     {
+        use crate::combat::weapon::AmmoType;
         use crate::items::pickup::Pickup::*;
         for _ in 0..(level + 1) {
             spawner.try_spawn_item(Apple, &mut rng);
         }
         spawner.try_spawn_item(MedPack, &mut rng);
 
+        for _ in 0..(level + 2) {
+            let ammo_type = AmmoType::ALL[rng.usize(..AmmoType::ALL.len())];
+            spawner.try_spawn_item(Ammo(ammo_type), &mut rng);
+        }
+
         let mut coins = get_coin_count(level, game_data.level_style);
         while coins > 0 {
             let (item, value) = if coins > 5 { (Gem, 5) } else { (Coin, 1) };
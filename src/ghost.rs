@@ -0,0 +1,352 @@
+use std::{cmp::Reverse, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::player::{InputState, Player},
+    game::{GameState, TICK_RATE},
+    physics::Collider,
+    render::{RenderResource, Sprite3d},
+    replay::{Recording, Replay, ReplayError},
+    CommandLineArgs, GameInfo, GameSettings,
+};
+
+/// The best daily runs are kept in this folder, one file per seed
+const GHOST_DIR: &str = "ghosts";
+/// How much of the ghost shows through
+const GHOST_ALPHA: f32 = 0.4;
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(Ghost::default())
+            .insert_resource(GhostTrack::default())
+            .add_systems(
+                OnExit(GameState::MainMenu),
+                start_ghost.after(crate::replay::start_recording),
+            )
+            .add_systems(OnEnter(GameState::MainMenu), (save_ghost, despawn_ghost))
+            .add_systems(
+                FixedUpdate,
+                record_ghost_frame
+                    .after(crate::lifecycle::check_ttl)
+                    .in_set(crate::game::TickSet::Cleanup),
+            )
+            .add_systems(Update, update_ghost.run_if(in_state(GameState::InGame)));
+    }
+}
+
+/// Where the player was on one tick of a run.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct GhostFrame {
+    pub pos: Vec3,
+    pub yaw: f32,
+    pub level: u8,
+}
+
+/// A daily run as its ghost plays it back, along with its replay.
+#[derive(Serialize, Deserialize, Default)]
+pub struct GhostRun {
+    pub track: Vec<GhostFrame>,
+    pub won: bool,
+    pub replay: Replay,
+}
+
+impl GhostRun {
+    fn path(seed: u64) -> PathBuf {
+        PathBuf::from(GHOST_DIR).join(format!("daily_{}.ron", seed))
+    }
+
+    /// Loads the best run for the seed, if it was played before.
+    pub fn load(seed: u64) -> Result<Option<Self>, ReplayError> {
+        let path = Self::path(seed);
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let text = std::fs::read_to_string(path).map_err(ReplayError::IO)?;
+        ron::from_str(&text).map(Some).map_err(ReplayError::Parse)
+    }
+
+    pub fn save(&self, seed: u64) -> Result<(), ReplayError> {
+        std::fs::create_dir_all(GHOST_DIR).map_err(ReplayError::IO)?;
+        let text = ron::to_string(self).map_err(ReplayError::Write)?;
+        std::fs::write(Self::path(seed), text).map_err(ReplayError::IO)
+    }
+
+    fn level(&self) -> u8 {
+        self.track.last().map_or(0, |frame| frame.level)
+    }
+
+    /// The first tick played on `level`.
+    pub fn level_start(&self, level: u8) -> Option<usize> {
+        self.track.iter().position(|frame| frame.level == level)
+    }
+
+    /// Runs that got further are better. Of two runs that got equally far, the one that got there first is.
+    pub fn beats(&self, other: &GhostRun) -> bool {
+        let key = |run: &GhostRun| {
+            let level = run.level();
+            let tick = if run.won {
+                run.track.len()
+            } else {
+                run.level_start(level).unwrap_or(0)
+            };
+            (run.won, level, Reverse(tick))
+        };
+        key(self) > key(other)
+    }
+}
+
+/// The best run of the daily seed that is being raced, and how far behind it the player is.
+#[derive(Resource, Default)]
+pub struct Ghost {
+    run: Option<GhostRun>,
+    /// Seconds the player took longer than the ghost to reach the current level. It is negative when ahead.
+    pub delta: Option<f32>,
+}
+
+/// The path of the current run, which becomes the ghost if it is the best daily run.
+#[derive(Resource, Default)]
+pub struct GhostTrack {
+    frames: Vec<GhostFrame>,
+    won: bool,
+}
+
+#[derive(Component)]
+pub struct GhostSprite;
+
+fn start_ghost(
+    game_settings: Res<GameSettings>,
+    cl_args: Res<CommandLineArgs>,
+    mut ghost: ResMut<Ghost>,
+    mut track: ResMut<GhostTrack>,
+) {
+    *track = GhostTrack::default();
+    *ghost = Ghost::default();
+
+    let Some(seed) = game_settings.map_seed else {
+        return;
+    };
+    if !game_settings.daily || !cl_args.ghost {
+        return;
+    }
+    match GhostRun::load(seed) {
+        Ok(run) => ghost.run = run,
+        Err(err) => warn!("Could not load the ghost. {}", err),
+    }
+}
+
+fn record_ghost_frame(
+    game: Res<GameInfo>,
+    state: Res<InputState>,
+    player_query: Query<&Collider, With<Player>>,
+    mut track: ResMut<GhostTrack>,
+    mut ghost: ResMut<Ghost>,
+) {
+    let Ok(collider) = player_query.get_single() else {
+        return;
+    };
+    track.won = game.won;
+    let frames = &mut track.frames;
+
+    if frames.last().is_some_and(|frame| frame.level != game.level) {
+        // Just got to the next level
+        let ghost_start = ghost
+            .run
+            .as_ref()
+            .and_then(|run| run.level_start(game.level));
+        ghost.delta =
+            ghost_start.map(|start| (frames.len() as f32 - start as f32) / TICK_RATE as f32);
+    }
+
+    frames.push(GhostFrame {
+        pos: collider.pos,
+        yaw: state.yaw,
+        level: game.level,
+    });
+}
+
+/// Keeps the best run of the daily seed.
+fn save_ghost(
+    game_settings: Res<GameSettings>,
+    recording: Res<Recording>,
+    mut track: ResMut<GhostTrack>,
+) {
+    let Some(seed) = game_settings.map_seed else {
+        return;
+    };
+    if !game_settings.daily || track.frames.is_empty() {
+        return;
+    }
+
+    let run = GhostRun {
+        track: std::mem::take(&mut track.frames),
+        won: track.won,
+        replay: recording.0.clone(),
+    };
+    match GhostRun::load(seed) {
+        Ok(Some(best)) if !run.beats(&best) => {}
+        _ => {
+            if let Err(err) = run.save(seed) {
+                warn!("Could not save the ghost. {}", err);
+            }
+        }
+    }
+}
+
+fn despawn_ghost(mut commands: Commands, query: Query<Entity, With<GhostSprite>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Moves the ghost to where the best run was after as many ticks, if it was on the same level.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)] // Not really applicable for bevy systems
+fn update_ghost(
+    mut commands: Commands,
+    ghost: Res<Ghost>,
+    game: Res<GameInfo>,
+    track: Res<GhostTrack>,
+    mut ghost_query: Query<
+        (
+            &mut Transform,
+            &mut Visibility,
+            &mut Sprite3d,
+            &mut Handle<Mesh>,
+        ),
+        With<GhostSprite>,
+    >,
+    camera_query: Query<&Transform, (With<Camera3d>, Without<GhostSprite>)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut render_res: ResMut<RenderResource>,
+) {
+    let Some(run) = &ghost.run else {
+        return;
+    };
+    let Ok((mut transform, mut visibility, mut sprite, mut mesh)) = ghost_query.get_single_mut()
+    else {
+        spawn_ghost(&mut commands, &mut meshes, &mut materials, &mut render_res);
+        return;
+    };
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+
+    let Some(frame) = run
+        .track
+        .get(track.frames.len())
+        .filter(|frame| frame.level == game.level)
+    else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Inherited;
+
+    // Face the camera, and turn to the side the ghost is looking at
+    let pos = Vec3::new(frame.pos.x, 0.5, frame.pos.z);
+    let mut to_camera = camera.translation - pos;
+    to_camera.y = 0.0;
+    transform.translation = pos;
+    transform.look_at(pos + to_camera, Vec3::Y);
+
+    let looking = Quat::from_rotation_y(frame.yaw) * Vec3::NEG_Z;
+    let flipped = looking.cross(to_camera).y > 0.0;
+    if sprite.flipped != flipped {
+        sprite.flipped = flipped;
+        *mesh = render_res.get_mesh(*sprite, &mut meshes);
+    }
+}
+
+fn spawn_ghost(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    render_res: &mut ResMut<RenderResource>,
+) {
+    let Some(seq) = render_res.sprites.misc.get("ghost.png") else {
+        return;
+    };
+    let sprite = Sprite3d::new(seq.tile_start());
+
+    let material = materials.add(StandardMaterial {
+        base_color: Color::rgba(1.0, 1.0, 1.0, GHOST_ALPHA),
+        base_color_texture: Some(render_res.sprites.texture.clone()),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+    commands.spawn((
+        GhostSprite,
+        sprite,
+        PbrBundle {
+            mesh: render_res.get_mesh(sprite, meshes),
+            material,
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(levels: &[(u8, usize)], won: bool) -> GhostRun {
+        let track = levels
+            .iter()
+            .flat_map(|(level, ticks)| {
+                std::iter::repeat_n(
+                    GhostFrame {
+                        pos: Vec3::ZERO,
+                        yaw: 0.0,
+                        level: *level,
+                    },
+                    *ticks,
+                )
+            })
+            .collect();
+        GhostRun {
+            track,
+            won,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn level_start() {
+        let run = run(&[(1, 100), (2, 50), (3, 10)], false);
+        assert_eq!(run.level_start(1), Some(0));
+        assert_eq!(run.level_start(2), Some(100));
+        assert_eq!(run.level_start(3), Some(150));
+        assert_eq!(run.level_start(4), None);
+    }
+
+    #[test]
+    fn further_is_better() {
+        let level2 = run(&[(1, 100), (2, 500)], false);
+        let level3 = run(&[(1, 300), (2, 300), (3, 10)], false);
+        assert!(level3.beats(&level2));
+        assert!(!level2.beats(&level3));
+
+        let won = run(&[(1, 900), (2, 900), (3, 900)], true);
+        assert!(won.beats(&level3));
+    }
+
+    #[test]
+    fn faster_is_better() {
+        // Only the time to get to the last level counts, not how long the player survived on it
+        let fast = run(&[(1, 100), (2, 10)], false);
+        let slow = run(&[(1, 200), (2, 500)], false);
+        assert!(fast.beats(&slow));
+        assert!(!slow.beats(&fast));
+        assert!(!fast.beats(&fast));
+
+        let fast = run(&[(1, 100), (2, 100)], true);
+        let slow = run(&[(1, 100), (2, 101)], true);
+        assert!(fast.beats(&slow));
+    }
+}
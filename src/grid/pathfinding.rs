@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::{cmp::Reverse, collections::BinaryHeap, collections::VecDeque};
 
 use super::{Coords, Grid};
 
@@ -39,3 +39,304 @@ where
     }
     (dirs, distances)
 }
+
+/// Cost of a horizontal or vertical step onto a tile with cost 1.
+pub const STEP_ORTHOGONAL: u32 = 10;
+/// Cost of a diagonal step onto a tile with cost 1. Roughly `STEP_ORTHOGONAL * sqrt(2)`.
+pub const STEP_DIAGONAL: u32 = 14;
+
+const DIRS8: [Coords; 8] = [
+    Coords::new(-1, -1),
+    Coords::new(0, -1),
+    Coords::new(1, -1),
+    Coords::new(-1, 0),
+    Coords::new(1, 0),
+    Coords::new(-1, 1),
+    Coords::new(0, 1),
+    Coords::new(1, 1),
+];
+
+/// Calls `f` for every passable neighbour of `pos`, together with the step length and the tile cost of that neighbour.
+/// Diagonal steps are only allowed if both orthogonal neighbours are passable, just like `FuzzyPath` in the AI.
+fn for_each_neighbour8<T, F, G>(map: &Grid<T>, cost: &F, pos: Coords, mut f: G)
+where
+    F: Fn(Coords, T) -> Option<u32>,
+    G: FnMut(Coords, u32, u32),
+    T: Copy,
+{
+    let passable = |c: Coords| -> Option<u32> {
+        if !map.contains_coord(c.x, c.z) {
+            return None;
+        }
+        cost(c, map[c])
+    };
+
+    for dir in DIRS8 {
+        let dest = pos + dir;
+        let Some(tile_cost) = passable(dest) else {continue;};
+
+        let step = if let Some((h, v)) = dir.split() {
+            if passable(pos + h).is_none() || passable(pos + v).is_none() {
+                continue; // Tile would require corner cutting
+            }
+            STEP_DIAGONAL
+        } else {
+            STEP_ORTHOGONAL
+        };
+
+        f(dest, step, tile_cost);
+    }
+}
+
+/// Lower bound of the cost between two tiles, assuming all tile costs are at least 1.
+fn octile_dist(p0: Coords, p1: Coords) -> u32 {
+    let dx = (p1.x - p0.x).unsigned_abs();
+    let dz = (p1.z - p0.z).unsigned_abs();
+    let (min, max) = if dx < dz { (dx, dz) } else { (dz, dx) };
+    STEP_DIAGONAL * min + STEP_ORTHOGONAL * (max - min)
+}
+
+/// Finds the cheapest 8-way path from `start` to `end` using A*. Returns all tiles from the start up to and including
+/// the end, and the cost from the start for every tile the search visited. Unvisited tiles are `u32::MAX`.
+///
+/// `cost` returns the cost multiplier for entering a tile, or `None` if the tile is impassable.
+/// Multipliers should be at least 1, otherwise the path is not guaranteed to be the cheapest.
+pub fn find_path8<T, F>(
+    map: &Grid<T>,
+    cost: F,
+    start: Coords,
+    end: Coords,
+) -> Option<(Vec<Coords>, Grid<u32>)>
+where
+    F: Fn(Coords, T) -> Option<u32>,
+    T: Copy,
+{
+    if !map.contains_coord(start.x, start.z) || !map.contains_coord(end.x, end.z) {
+        return None;
+    }
+
+    let mut distances = Grid::<u32>::new_from(map.x_max(), map.z_max(), u32::MAX);
+    let mut came_from = Grid::<Coords>::new_from(map.x_max(), map.z_max(), Coords::INVALID);
+    let mut open = BinaryHeap::new();
+
+    distances[start] = 0;
+    open.push(Reverse((octile_dist(start, end), 0, start.x, start.z)));
+
+    while let Some(Reverse((_, dist, x, z))) = open.pop() {
+        let pos = Coords::new(x, z);
+        if dist > distances[pos] {
+            continue; // Outdated entry
+        }
+
+        if pos == end {
+            let mut path = vec![end];
+            let mut cur = end;
+            while cur != start {
+                cur = came_from[cur];
+                path.push(cur);
+            }
+            path.reverse();
+
+            return Some((path, distances));
+        }
+
+        for_each_neighbour8(map, &cost, pos, |next, step, tile_cost| {
+            let next_dist = dist + step * tile_cost;
+            if next_dist < distances[next] {
+                distances[next] = next_dist;
+                came_from[next] = pos;
+                open.push(Reverse((
+                    next_dist + octile_dist(next, end),
+                    next_dist,
+                    next.x,
+                    next.z,
+                )));
+            }
+        });
+    }
+    None
+}
+
+/// Distances towards the nearest source tile, for all tiles on the map.
+pub struct DistanceField {
+    pub distances: Grid<u32>,
+}
+
+impl DistanceField {
+    pub fn get(&self, pos: Coords) -> u32 {
+        if self.distances.contains_coord(pos.x, pos.z) {
+            self.distances[pos]
+        } else {
+            u32::MAX
+        }
+    }
+
+    pub fn is_reachable(&self, pos: Coords) -> bool {
+        self.get(pos) != u32::MAX
+    }
+}
+
+/// Builds a Dijkstra map with 8-way movement, containing the cost of walking from each tile to the nearest of the `sources`.
+///
+/// `cost` works the same as in `find_path8`, so `field.get(pos)` equals the cost of the `find_path8` path from `pos` to the nearest source.
+pub fn distance_field8<T, F>(map: &Grid<T>, cost: F, sources: &[Coords]) -> DistanceField
+where
+    F: Fn(Coords, T) -> Option<u32>,
+    T: Copy,
+{
+    distance_field8_within(map, cost, sources, u32::MAX)
+}
+
+/// Same as `distance_field8`, but stops searching at `max_dist`. Tiles that are further away are unreachable.
+pub fn distance_field8_within<T, F>(
+    map: &Grid<T>,
+    cost: F,
+    sources: &[Coords],
+    max_dist: u32,
+) -> DistanceField
+where
+    F: Fn(Coords, T) -> Option<u32>,
+    T: Copy,
+{
+    let mut distances = Grid::<u32>::new_from(map.x_max(), map.z_max(), u32::MAX);
+    let mut open = BinaryHeap::new();
+
+    for source in sources {
+        if !map.contains_coord(source.x, source.z) || cost(*source, map[*source]).is_none() {
+            continue;
+        }
+        distances[*source] = 0;
+        open.push(Reverse((0, source.x, source.z)));
+    }
+
+    while let Some(Reverse((dist, x, z))) = open.pop() {
+        let pos = Coords::new(x, z);
+        if dist > distances[pos] {
+            continue; // Outdated entry
+        }
+
+        // The field is walked towards the sources, so the cost is paid for entering `pos`, not `next`.
+        let Some(tile_cost) = cost(pos, map[pos]) else {continue;};
+        for_each_neighbour8(map, &cost, pos, |next, step, _| {
+            let next_dist = dist + step * tile_cost;
+            if next_dist < distances[next] && next_dist <= max_dist {
+                distances[next] = next_dist;
+                open.push(Reverse((next_dist, next.x, next.z)));
+            }
+        });
+    }
+
+    DistanceField { distances }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_grid(rows: &[&str]) -> Grid<char> {
+        let mut grid = Grid::new_from(rows[0].len() as i32, rows.len() as i32, '#');
+        for (z, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                grid[(x as i32, z as i32)] = c;
+            }
+        }
+        grid
+    }
+
+    fn tile_cost(_: Coords, c: char) -> Option<u32> {
+        match c {
+            '#' => None,
+            '~' => Some(5),
+            _ => Some(1),
+        }
+    }
+
+    const fn coord(x: i32, z: i32) -> Coords {
+        Coords::new(x, z)
+    }
+
+    #[test]
+    fn path_straight() {
+        let grid = make_grid(&["#####", "#...#", "#####"]);
+        let (path, distances) = find_path8(&grid, tile_cost, coord(1, 1), coord(3, 1)).unwrap();
+
+        assert_eq!(path, vec![coord(1, 1), coord(2, 1), coord(3, 1)]);
+        assert_eq!(distances[coord(3, 1)], 2 * STEP_ORTHOGONAL);
+        assert_eq!(distances[coord(1, 1)], 0);
+    }
+
+    #[test]
+    fn path_diagonal() {
+        let grid = make_grid(&["######", "#....#", "#....#", "#....#", "#....#", "######"]);
+        let (path, distances) = find_path8(&grid, tile_cost, coord(1, 1), coord(4, 4)).unwrap();
+
+        assert_eq!(path.len(), 4);
+        assert_eq!(distances[coord(4, 4)], 3 * STEP_DIAGONAL);
+    }
+
+    #[test]
+    fn path_no_corner_cutting() {
+        let grid = make_grid(&["####", "#..#", "##.#", "####"]);
+        let (path, distances) = find_path8(&grid, tile_cost, coord(1, 1), coord(2, 2)).unwrap();
+
+        assert_eq!(path, vec![coord(1, 1), coord(2, 1), coord(2, 2)]);
+        assert_eq!(distances[coord(2, 2)], 2 * STEP_ORTHOGONAL);
+    }
+
+    #[test]
+    fn path_unreachable() {
+        let grid = make_grid(&["#####", "#.#.#", "#####"]);
+        assert!(find_path8(&grid, tile_cost, coord(1, 1), coord(3, 1)).is_none());
+        assert!(find_path8(&grid, tile_cost, coord(1, 1), coord(9, 9)).is_none());
+    }
+
+    #[test]
+    fn path_avoids_expensive_tiles() {
+        let grid = make_grid(&["#####", "#.~.#", "#...#", "#####"]);
+        let (path, distances) = find_path8(&grid, tile_cost, coord(1, 1), coord(3, 1)).unwrap();
+
+        assert!(!path.contains(&coord(2, 1)));
+        assert_eq!(distances[coord(3, 1)], 2 * STEP_DIAGONAL);
+    }
+
+    #[test]
+    fn field_multiple_sources() {
+        let grid = make_grid(&["#######", "#.....#", "#######"]);
+        let field = distance_field8(&grid, tile_cost, &[coord(1, 1), coord(5, 1)]);
+
+        assert_eq!(field.get(coord(1, 1)), 0);
+        assert_eq!(field.get(coord(2, 1)), STEP_ORTHOGONAL);
+        assert_eq!(field.get(coord(3, 1)), 2 * STEP_ORTHOGONAL);
+        assert_eq!(field.get(coord(4, 1)), STEP_ORTHOGONAL);
+        assert!(!field.is_reachable(coord(0, 0)));
+        assert!(!field.is_reachable(coord(-1, 0)));
+    }
+
+    #[test]
+    fn field_matches_astar() {
+        let grid = make_grid(&[
+            "########", "#..~...#", "#.##.#.#", "#..~.#.#", "#.##...#", "########",
+        ]);
+        let end = coord(6, 4);
+        let field = distance_field8(&grid, tile_cost, &[end]);
+
+        for pos in grid.size().iter() {
+            if grid[pos] == '#' {
+                assert!(!field.is_reachable(pos));
+                continue;
+            }
+
+            let (_, distances) = find_path8(&grid, tile_cost, pos, end).unwrap();
+            assert_eq!(distances[end], field.get(pos), "At {:?}", pos);
+        }
+    }
+
+    #[test]
+    fn field_max_dist() {
+        let grid = make_grid(&["#######", "#.....#", "#######"]);
+        let field = distance_field8_within(&grid, tile_cost, &[coord(1, 1)], 2 * STEP_ORTHOGONAL);
+
+        assert_eq!(field.get(coord(3, 1)), 2 * STEP_ORTHOGONAL);
+        assert!(!field.is_reachable(coord(4, 1)));
+    }
+}
@@ -0,0 +1,379 @@
+use std::time::Duration;
+
+use bevy::{app::AppExit, prelude::*, time::TimeUpdateStrategy};
+
+use crate::{
+    combat::{player::Player, CreatureStats},
+    game::{GameState, TICK_RATE},
+    render::{spritemap::SpriteSeq, RenderResource},
+    replay::Playback,
+    ui::menus::{MenuInfo, OnClick},
+    CommandLineArgs, GameInfo,
+};
+
+/// Builds the game without a window, renderer or audio. Every update advances the game by exactly one tick, so it
+/// runs as fast as the machine allows.
+pub fn headless_app(args: CommandLineArgs) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        bevy::input::InputPlugin,
+    ))
+    .init_asset::<Mesh>()
+    .init_asset::<StandardMaterial>()
+    .init_asset::<Image>()
+    .init_asset::<AudioSource>()
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+        1.0 / TICK_RATE,
+    )))
+    .insert_resource(args)
+    .insert_resource(MenuInfo::main_menu())
+    .add_event::<OnClick>()
+    .add_state::<GameState>()
+    .add_plugins((
+        crate::game::GamePlugin,
+        crate::combat::CombatPlugin,
+        crate::replay::ReplayPlugin,
+    ))
+    .add_systems(Startup, headless_setup)
+    .add_systems(
+        Update,
+        // Replays press the menu buttons, like going to the next level
+        crate::ui::menus::button_press.run_if(not(in_state(GameState::InGame))),
+    );
+    app
+}
+
+/// Plays the replay given with `--replay` without showing it, and prints how the run ended.
+pub fn run(args: CommandLineArgs) {
+    if args.replay.is_none() {
+        eprintln!("Headless mode needs a replay to play. Pass one with `--replay <file>`.");
+        return;
+    }
+
+    let mut app = headless_app(args);
+    app.add_systems(Update, exit_after_replay);
+    app.run();
+}
+
+fn headless_setup(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut render_res: ResMut<RenderResource>,
+) {
+    // Nothing is drawn, so every sprite uses the same placeholder
+    let placeholder = SpriteSeq {
+        x: 0..1,
+        ..default()
+    };
+    for name in [
+        "no_block.png",
+        "no_item.png",
+        "no_monster.png",
+        "no_projectile.png",
+        "vending_machine.png",
+    ] {
+        render_res
+            .sprites
+            .misc
+            .insert(name.to_string(), placeholder.clone());
+    }
+    render_res.material = materials.add(StandardMaterial::default());
+
+    commands.spawn(Camera3dBundle::default());
+}
+
+fn exit_after_replay(
+    playback: Option<Res<Playback>>,
+    game: Res<GameInfo>,
+    player_query: Query<&CreatureStats, With<Player>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if !playback.is_some_and(|p| p.is_finished()) {
+        return;
+    }
+
+    let hp = player_query.get_single().map_or(0, |stats| stats.hp);
+    println!(
+        "Replay finished on level {} with score {}, {} coins and {} hp",
+        game.level, game.score, game.coins, hp
+    );
+    exit.send(AppExit);
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{combat::player::InputAction, grid::Coords};
+
+    /// Runs a game for tests, one tick at a time.
+    pub struct Sim {
+        pub app: App,
+    }
+
+    impl Sim {
+        /// Starts a run right away, with the same command line arguments as the game, like `["--seed", "3"]`.
+        pub fn new(args: &[&str]) -> Self {
+            use clap::Parser;
+
+            let args = CommandLineArgs::parse_from(
+                std::iter::once("cobblehelm_maze").chain(args.iter().copied()),
+            );
+            let settings = crate::GameSettings::from_cl(&args);
+            let mut app = headless_app(args);
+            app.insert_resource(settings);
+            app.world
+                .resource_mut::<NextState<GameState>>()
+                .set(GameState::InGame);
+
+            // Loads the definitions and the level. Time doesn't move during the first update.
+            app.update();
+            Self { app }
+        }
+
+        /// Plays one tick, with the actions held or pressed during it.
+        pub fn tick(&mut self, actions: &[InputAction]) {
+            let mut input = self
+                .app
+                .world
+                .resource_mut::<crate::combat::player::TickInput>();
+            for act in actions {
+                input.push(*act);
+            }
+            self.app.update();
+        }
+
+        /// Presses a menu button and waits for the game to get to the next state.
+        pub fn click(&mut self, click: OnClick) {
+            self.app.world.send_event(click);
+            self.app.update();
+            self.app.update();
+        }
+
+        pub fn run(&mut self, seconds: f32, actions: &[InputAction]) {
+            for _ in 0..(seconds * TICK_RATE as f32).round() as usize {
+                self.tick(actions);
+            }
+        }
+
+        pub fn player(&self) -> Entity {
+            self.app.world.resource::<GameInfo>().player.unwrap()
+        }
+
+        pub fn player_stats(&self) -> &CreatureStats {
+            self.app.world.get(self.player()).unwrap()
+        }
+
+        pub fn player_pos(&self) -> Vec3 {
+            self.app
+                .world
+                .get::<crate::physics::Collider>(self.player())
+                .unwrap()
+                .pos
+        }
+
+        pub fn state(&self) -> GameState {
+            *self.app.world.resource::<State<GameState>>().get()
+        }
+
+        /// Spawns a monster from `monsters.ron` at `pos`.
+        pub fn spawn_monster(&mut self, pos: Coords, name: &str) -> Entity {
+            use crate::combat::{monsterdefs::MonsterDefs, weapondefs::WeaponDefs};
+            use bevy::ecs::system::RunSystemOnce;
+
+            let monster = self.app.world.resource::<MonsterDefs>().find(name).unwrap();
+            self.app.world.run_system_once(
+                move |commands: Commands,
+                      map_data: ResMut<crate::map::MapData>,
+                      meshes: ResMut<Assets<Mesh>>,
+                      render_res: ResMut<RenderResource>,
+                      monster_defs: Res<MonsterDefs>,
+                      weapon_defs: Res<WeaponDefs>| {
+                    let mut spawner = crate::spawner::Spawner {
+                        commands,
+                        map_data,
+                        meshes,
+                        render_res,
+                        monster_defs,
+                        weapon_defs,
+                    };
+                    spawner.spawn_monster_at_pos(pos, monster, &mut fastrand::Rng::with_seed(0))
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn starts_level() {
+        let sim = Sim::new(&["--seed", "3"]);
+        assert_eq!(sim.state(), GameState::InGame);
+        assert_eq!(sim.app.world.resource::<GameInfo>().level, 1);
+        let stats = sim.player_stats();
+        assert_eq!(stats.hp, stats.hp_max);
+    }
+
+    #[test]
+    fn same_input_same_game() {
+        let play = || {
+            let mut sim = Sim::new(&["--seed", "7"]);
+            sim.run(1.0, &[InputAction::Forward, InputAction::Fire]);
+            sim.run(0.5, &[InputAction::RotLeft, InputAction::Sprint]);
+            sim.run(1.0, &[InputAction::Backward, InputAction::Right]);
+            (sim.player_pos(), sim.player_stats().hp)
+        };
+        assert_eq!(play(), play());
+    }
+
+    #[test]
+    fn tick_order_is_unambiguous() {
+        use bevy::ecs::schedule::{LogLevel, ScheduleBuildSettings};
+
+        let mut sim = Sim::new(&["--seed", "3"]);
+        sim.app.edit_schedule(FixedUpdate, |schedule| {
+            schedule.set_build_settings(ScheduleBuildSettings {
+                ambiguity_detection: LogLevel::Error,
+                ..default()
+            });
+        });
+        // Panics when systems that share data have no order between them
+        sim.tick(&[]);
+    }
+
+    #[test]
+    fn walks_forward() {
+        let mut sim = Sim::new(&["--seed", "3"]);
+        let start = sim.player_pos();
+        sim.run(0.5, &[InputAction::Forward]);
+        assert!(sim.player_pos().distance(start) > 0.1);
+    }
+
+    #[test]
+    fn crosses_to_next_level() {
+        use crate::{interactable::Interactable, physics::Collider};
+
+        let mut sim = Sim::new(&["--seed", "3"]);
+        let (portal, style) = sim
+            .app
+            .world
+            .query::<(&Collider, &Interactable)>()
+            .iter(&sim.app.world)
+            .find_map(|(collider, interactable)| match interactable {
+                Interactable::NextLevel(style) => Some((collider.pos, *style)),
+                _ => None,
+            })
+            .unwrap();
+
+        // Stand right in front of the portal
+        let player = sim.player();
+        let mut transform = sim.app.world.get_mut::<Transform>(player).unwrap();
+        transform.translation = portal - transform.forward() * 0.7;
+        let pos = transform.translation;
+        sim.app.world.get_mut::<Collider>(player).unwrap().pos = pos;
+        sim.tick(&[InputAction::Interact]);
+        sim.app.update();
+        assert_eq!(sim.state(), GameState::GameMenu);
+
+        sim.click(OnClick::NextLevel(style));
+        assert_eq!(sim.state(), GameState::InGame);
+        assert_eq!(sim.app.world.resource::<GameInfo>().level, 2);
+        sim.run(0.5, &[InputAction::Forward]);
+    }
+
+    #[test]
+    fn replay_continues_after_menu() {
+        use crate::replay::{Replay, TickRecord};
+
+        let mut sim = Sim::new(&["--seed", "3"]);
+        let mut ticks = vec![TickRecord::default(); 20];
+        ticks[5].actions.push(InputAction::Pause);
+        sim.app.insert_resource(Playback::new(Replay {
+            ticks,
+            clicks: vec![(6, OnClick::Resume)],
+            ..default()
+        }));
+
+        for _ in 0..40 {
+            sim.app.update();
+        }
+        assert!(sim.app.world.resource::<Playback>().is_finished());
+        assert_eq!(sim.state(), GameState::InGame);
+    }
+
+    #[test]
+    fn goblin_hurts_player() {
+        let mut sim = Sim::new(&["--seed", "3"]);
+        let player = Coords::from_vec(sim.player_pos());
+        let map_data = sim.app.world.resource::<crate::map::MapData>();
+        let dir = [
+            Coords::new(1, 0),
+            Coords::new(-1, 0),
+            Coords::new(0, 1),
+            Coords::new(0, -1),
+        ]
+        .into_iter()
+        .find(|dir| !map_data.solid_map[player + *dir])
+        .unwrap();
+        sim.spawn_monster(player + dir, "goblin");
+
+        sim.run(2.0, &[]);
+        let stats = sim.player_stats();
+        assert!(stats.hp < stats.hp_max);
+    }
+
+    #[test]
+    fn killed_once() {
+        use crate::combat::{DamageEvent, DamageType};
+
+        let mut sim = Sim::new(&["--seed", "3"]);
+        let player = Coords::from_vec(sim.player_pos());
+        let map_data = sim.app.world.resource::<crate::map::MapData>();
+        let pos = [Coords::new(1, 0), Coords::new(-1, 0), Coords::new(0, 1)]
+            .into_iter()
+            .map(|dir| player + dir)
+            .find(|pos| !map_data.solid_map[*pos])
+            .unwrap();
+        let goblin = sim.spawn_monster(pos, "goblin");
+        let hit = || DamageEvent {
+            instigator: None,
+            target: goblin,
+            damage: 100,
+            dam_type: DamageType::Normal,
+        };
+        sim.app.world.send_event(hit());
+        sim.app.world.send_event(hit());
+
+        sim.tick(&[]);
+        assert_eq!(sim.app.world.resource::<GameInfo>().score, 20);
+        assert!(sim.app.world.get::<CreatureStats>(goblin).is_none());
+
+        // Nor can it be killed again while it is dying
+        sim.app.world.send_event(hit());
+        sim.tick(&[]);
+        assert_eq!(sim.app.world.resource::<GameInfo>().score, 20);
+    }
+
+    #[test]
+    fn goblins_only_swing_at_player() {
+        let mut sim = Sim::new(&["--seed", "3"]);
+        let player = Coords::from_vec(sim.player_pos());
+        let map_data = sim.app.world.resource::<crate::map::MapData>();
+        let dir = [
+            Coords::new(1, 0),
+            Coords::new(-1, 0),
+            Coords::new(0, 1),
+            Coords::new(0, -1),
+        ]
+        .into_iter()
+        .find(|dir| !map_data.solid_map[player + *dir] && !map_data.solid_map[player + *dir + *dir])
+        .unwrap();
+        sim.spawn_monster(player + dir, "goblin");
+        let behind = sim.spawn_monster(player + dir + dir, "goblin");
+
+        sim.run(2.0, &[]);
+        let stats = sim.player_stats();
+        assert!(stats.hp < stats.hp_max);
+        let stats = sim.app.world.get::<CreatureStats>(behind).unwrap();
+        assert_eq!(stats.hp, stats.hp_max);
+    }
+}
@@ -11,11 +11,12 @@ use bevy::{
 };
 
 use crate::{
+    combat::ai::FlowFields,
     grid::Coords,
     map::{DoorType, MapData},
     mapgen::style::LevelStyle,
     physics::Collider,
-    render::{spritemap::SpriteSeq, RenderResource, Sprite3d},
+    render::{spritemap::SpriteSeq, RenderResource, Sprite3d, Tint},
     GameInfo,
 };
 
@@ -87,6 +88,7 @@ impl Door {
             tile: self.sprite(),
             flipped: false,
             two_sided: true,
+            tint: Tint::None,
         }
     }
 }
@@ -97,7 +99,7 @@ pub fn update_doors(
     mut meshes: ResMut<Assets<Mesh>>,
     mut render_res: ResMut<RenderResource>,
     game_info: Res<GameInfo>,
-
+    mut flow_fields: ResMut<FlowFields>,
     mut map: ResMut<MapData>,
 ) {
     for event in events.read() {
@@ -108,8 +110,14 @@ pub fn update_doors(
                 continue;
             }
 
+            // Only the player carries keys. Other instigators, like monsters, can only open unlocked doors.
+            let key_flags = match event.instigator {
+                Some(instigator) if Some(instigator) != game_info.player => 0,
+                _ => game_info.key_flags,
+            };
+
             // Locked doors
-            if (door.required_key & !game_info.key_flags) != 0 {
+            if (door.required_key & !key_flags) != 0 {
                 // Door cannot be opened, because it is locked
 
                 // TODO: Play nope sound
@@ -124,6 +132,7 @@ pub fn update_doors(
             let pos = Coords::from_vec(transform.translation);
 
             door.update_collision(pos, &mut map);
+            flow_fields.clear();
         }
     }
 }
@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::pickup::Pickup;
+use crate::{
+    combat::{monsterdefs::MonsterDefs, weapondefs::WeaponDefs, DeathEvent},
+    spawner::Spawner,
+};
+
+/// Items are scattered this far around the spot where the monster died.
+const LOOT_SCATTER: f32 = 0.3;
+
+/// An entry of a loot table in `assets/data/monsters.ron`. Each entry is rolled separately.
+#[derive(Deserialize)]
+pub struct LootDrop {
+    pub item: Pickup,
+    pub chance: f32,
+    #[serde(default = "LootDrop::default_count")]
+    pub count: u8,
+}
+
+impl LootDrop {
+    fn default_count() -> u8 {
+        1
+    }
+}
+
+pub fn roll_loot(table: &[LootDrop], rng: &mut fastrand::Rng) -> Vec<Pickup> {
+    let mut items = vec![];
+    for drop in table {
+        if rng.f32() < drop.chance {
+            items.extend(std::iter::repeat_n(drop.item, drop.count as usize));
+        }
+    }
+    items
+}
+
+/// Loot is rolled with its own RNG, seeded by the level, so it is the same when a seed is replayed.
+#[derive(Resource, Default)]
+pub struct LootRng(pub fastrand::Rng);
+
+#[allow(clippy::too_many_arguments)] // Not really applicable for bevy systems
+pub fn drop_loot(
+    mut ev_death: EventReader<DeathEvent>,
+    mut loot_rng: ResMut<LootRng>,
+    commands: Commands,
+    map_data: ResMut<crate::map::MapData>,
+    meshes: ResMut<Assets<Mesh>>,
+    render_res: ResMut<crate::render::RenderResource>,
+    monster_defs: Res<MonsterDefs>,
+    weapon_defs: Res<WeaponDefs>,
+) {
+    let mut spawner = Spawner {
+        commands,
+        map_data,
+        meshes,
+        render_res,
+        monster_defs,
+        weapon_defs,
+    };
+    let rng = &mut loot_rng.0;
+
+    for ev in ev_death.read() {
+        let table = &spawner.monster_defs.get(ev.monster_type).loot;
+        for item in roll_loot(table, rng) {
+            let offset = Vec3::new(rng.f32() - 0.5, 0.0, rng.f32() - 0.5) * (LOOT_SCATTER * 2.0);
+            spawner.spawn_item_at(ev.pos + offset, item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Vec<LootDrop> {
+        ron::from_str("[(item: Coin, chance: 1.0, count: 3), (item: Key(2), chance: 0.0), (item: Ammo(Snow), chance: 0.5)]")
+            .unwrap()
+    }
+
+    #[test]
+    fn guaranteed_drops() {
+        let items = roll_loot(&table(), &mut fastrand::Rng::with_seed(1));
+        assert_eq!(&items[..3], &[Pickup::Coin; 3]);
+        assert!(!items.contains(&Pickup::Key(2)));
+    }
+
+    #[test]
+    fn seeded_rolls() {
+        let table = table();
+        for seed in 0..20 {
+            let items0 = roll_loot(&table, &mut fastrand::Rng::with_seed(seed));
+            let items1 = roll_loot(&table, &mut fastrand::Rng::with_seed(seed));
+            assert_eq!(items0, items1);
+        }
+    }
+}
@@ -1 +1,2 @@
+pub mod loot;
 pub mod pickup;
@@ -1,29 +1,28 @@
 use bevy::prelude::*;
+use serde::Deserialize;
 
 use crate::{
-    combat::{player::Player, CreatureStats},
-    physics::Collider,
-    render::{spritemap::USprite, Sprite3d},
-    ui::menus::{MenuInfo, MenuType},
+    combat::{arsenal::Arsenal, player::Player, weapon::AmmoType, CreatureStats},
+    physics::{Collider, SpatialIndex},
+    render::{spritemap::USprite, Sprite3d, Tint},
     GameInfo,
 };
 
-#[derive(Component, Clone, Copy, PartialEq, Debug)]
+#[derive(Component, Clone, Copy, PartialEq, Debug, Deserialize)]
 pub enum Pickup {
     Apple,
     MedPack,
     Coin,
     Gem,
-    Phylactery,
     Key(u8),
+    Ammo(AmmoType),
 }
 
 enum StatGain {
     PercHealth(i16),
     Coins(i32),
-    Phylactery,
     Key(u8),
-    //Ammo(u8,i16),
+    Ammo(AmmoType, i16),
 }
 
 impl Pickup {
@@ -34,13 +33,14 @@ impl Pickup {
             Pickup::Coin => StatGain::Coins(1),
             Pickup::Gem => StatGain::Coins(5),
             Pickup::Key(id) => StatGain::Key(1 << id),
-            Pickup::Phylactery => StatGain::Phylactery,
+            Pickup::Ammo(ammo_type) => StatGain::Ammo(ammo_type, ammo_type.pickup_amount()),
         }
     }
 
-    const fn can_take(self, stats: &CreatureStats) -> bool {
+    fn can_take(self, stats: &CreatureStats, arsenal: &Arsenal) -> bool {
         match self.to_stat_gain() {
             StatGain::PercHealth(_) => stats.hp < stats.hp_max,
+            StatGain::Ammo(ammo_type, _) => arsenal.ammo(ammo_type) < ammo_type.max(),
             _ => true,
         }
     }
@@ -48,9 +48,8 @@ impl Pickup {
     fn take(
         &self,
         game_info: &mut GameInfo,
-        menu_info: &mut MenuInfo,
         stats: &mut Mut<CreatureStats>,
-        game_state: &mut ResMut<NextState<crate::game::GameState>>,
+        arsenal: &mut Mut<Arsenal>,
     ) {
         match self.to_stat_gain() {
             StatGain::PercHealth(perc) => {
@@ -63,9 +62,8 @@ impl Pickup {
             StatGain::Key(mask) => {
                 game_info.key_flags |= mask;
             }
-            StatGain::Phylactery => {
-                game_state.set(crate::game::GameState::GameMenu);
-                menu_info.set(MenuType::Victory);
+            StatGain::Ammo(ammo_type, amount) => {
+                arsenal.add_ammo(ammo_type, amount);
             }
         }
         game_info.score += self.get_score(game_info.level as i32);
@@ -76,7 +74,7 @@ impl Pickup {
             StatGain::PercHealth(_) => 0,
             StatGain::Coins(count) => count * 25,
             StatGain::Key(_) => level * 100,
-            StatGain::Phylactery => 5000,
+            StatGain::Ammo(_, _) => 0,
         }
     }
 
@@ -85,7 +83,7 @@ impl Pickup {
             StatGain::PercHealth(_) => Some("audio/pickup_heal.ogg"),
             StatGain::Coins(_) => Some("audio/pickup_coins.ogg"),
             StatGain::Key(_) => Some("audio/pickup_key.ogg"),
-            StatGain::Phylactery => Some("audio/phylactery.ogg"),
+            StatGain::Ammo(_, _) => None,
         }
     }
 
@@ -96,33 +94,34 @@ impl Pickup {
             Pickup::Coin => ("coin.png", 0),
             Pickup::Gem => ("gem.png", 0),
             Pickup::Key(id) => ("key.png", *id as USprite),
-            Pickup::Phylactery => ("phylactery.png", 0),
+            Pickup::Ammo(ammo_type) => ("ammo.png", *ammo_type as USprite),
         };
         Sprite3d {
             tile: tiles.get_item(str).tile(id),
             flipped: false,
             two_sided: false,
+            tint: Tint::None,
         }
     }
 }
 
 pub fn check_pickups(
     mut commands: Commands,
-    mut player_query: Query<(&Collider, &mut CreatureStats), With<Player>>,
-    mut pickup_query: Query<(Entity, &Pickup, &Collider)>,
+    mut player_query: Query<(&Collider, &mut CreatureStats, &mut Arsenal), With<Player>>,
+    pickup_query: Query<(Entity, &Pickup, &Collider)>,
+    spatial_index: Res<SpatialIndex>,
     mut game: ResMut<crate::GameInfo>,
-    mut game_state: ResMut<NextState<crate::game::GameState>>,
     asset_server: Res<AssetServer>,
-    mut menu_info: ResMut<MenuInfo>,
 ) {
-    for (player_body, mut stats) in player_query.iter_mut() {
-        for (pickup_entity, pickup, pickup_body) in pickup_query.iter_mut() {
+    for (player_body, mut stats, mut arsenal) in player_query.iter_mut() {
+        for (entity, _) in spatial_index.nearby(player_body.pos, player_body.radius) {
+            let Ok((pickup_entity, pickup, pickup_body)) = pickup_query.get(entity) else {continue;};
             if !player_body.collide_other(pickup_body) {
                 continue;
             }
 
-            if pickup.can_take(&stats) {
-                pickup.take(&mut game, &mut menu_info, &mut stats, &mut game_state);
+            if pickup.can_take(&stats, &arsenal) {
+                pickup.take(&mut game, &mut stats, &mut arsenal);
 
                 if let Some(filename) = pickup.to_sound() {
                     commands.spawn(AudioBundle {
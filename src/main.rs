@@ -1,6 +1,8 @@
 mod combat;
 mod game;
+mod ghost;
 mod grid;
+mod headless;
 mod interactable;
 mod items;
 mod lifecycle;
@@ -8,6 +10,7 @@ mod map;
 mod mapgen;
 mod physics;
 mod render;
+mod replay;
 mod spawner;
 mod spawnobject;
 mod ui;
@@ -17,6 +20,7 @@ use bevy::{prelude::*, time::Stopwatch};
 
 use clap::Parser;
 use mapgen::style::LevelStyle;
+use serde::{Deserialize, Serialize};
 
 #[derive(Parser, Resource, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -43,6 +47,26 @@ pub struct CommandLineArgs {
 
     #[arg(long)]
     difficulty: Option<f32>,
+
+    /// Lets stray shots of monsters hurt other monsters, who fight back
+    #[arg(long, default_value_t = false)]
+    infighting: bool,
+
+    /// Saves each run to this replay file when it ends
+    #[arg(long)]
+    record: Option<std::path::PathBuf>,
+
+    /// Plays a replay file saved with `--record`
+    #[arg(long)]
+    replay: Option<std::path::PathBuf>,
+
+    /// Races a ghost of your best daily run
+    #[arg(long, default_value_t = false)]
+    ghost: bool,
+
+    /// Plays the replay without a window or sound, and prints how the run ended
+    #[arg(long, default_value_t = false)]
+    headless: bool,
 }
 
 fn main() {
@@ -66,6 +90,11 @@ fn main() {
         std::env::set_var("RUST_BACKTRACE", "full");
     }
 
+    if args.headless {
+        headless::run(args);
+        return;
+    }
+
     App::new()
         .add_plugins(
             DefaultPlugins
@@ -81,7 +110,13 @@ fn main() {
         )
         .insert_resource(args)
         .add_state::<game::GameState>()
-        .add_plugins((ui::UIPlugin, game::GamePlugin, combat::CombatPlugin))
+        .add_plugins((
+            ui::UIPlugin,
+            game::GamePlugin,
+            combat::CombatPlugin,
+            replay::ReplayPlugin,
+            ghost::GhostPlugin,
+        ))
         .add_systems(Startup, app_setup)
         .add_systems(Update, make_tileset_async)
         .run();
@@ -136,6 +171,9 @@ pub struct GameInfo {
     pub time: Stopwatch,
     pub key_flags: u8,
     pub cheater: bool,
+    /// Whether the current level has a boss that needs to be defeated to win the game.
+    pub boss_fight: bool,
+    pub won: bool,
 }
 
 impl Default for GameInfo {
@@ -150,6 +188,8 @@ impl Default for GameInfo {
             time: Stopwatch::default(),
             key_flags: 0,
             cheater: false,
+            boss_fight: false,
+            won: false,
         }
     }
 }
@@ -181,10 +221,16 @@ impl GameInfo {
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Clone, Serialize, Deserialize)]
 pub struct GameSettings {
     pub map_seed: Option<u64>,
     pub difficulty: f32,
+    /// Whether this is the run of the day, which everybody plays with the same seed
+    #[serde(default)]
+    pub daily: bool,
+    /// Whether monsters hurt each other
+    #[serde(default)]
+    pub infighting: bool,
 }
 
 impl Default for GameSettings {
@@ -192,6 +238,8 @@ impl Default for GameSettings {
         Self {
             map_seed: None,
             difficulty: 1.0,
+            daily: false,
+            infighting: false,
         }
     }
 }
@@ -210,6 +258,8 @@ impl GameSettings {
         Self {
             map_seed: Some(seed.0),
             difficulty: 1.0,
+            daily: true,
+            infighting: false,
         }
     }
 
@@ -217,6 +267,8 @@ impl GameSettings {
         Self {
             map_seed: args.seed,
             difficulty: args.difficulty.unwrap_or(1.0),
+            daily: false,
+            infighting: args.infighting,
         }
     }
 }
@@ -1,5 +1,8 @@
-use crate::{grid::Grid, render::spritemap::SpriteSeq};
-use bevy::prelude::{Resource, Transform, Vec3};
+use crate::{
+    grid::{Coords, Grid},
+    render::spritemap::SpriteSeq,
+};
+use bevy::prelude::{Entity, Resource, Transform, Vec3};
 
 #[derive(Default, Copy, Clone, PartialEq, Eq)]
 pub enum Tile {
@@ -27,13 +30,15 @@ pub enum WallTile {
     Wood1,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum FloorTile {
     Sand,
     BrownFloor,
     GrayFloor,
     RainbowTiles,
     Ice,
+    /// Burns everybody standing on it
+    Lava,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -54,6 +59,19 @@ impl DoorType {
     }
 }
 
+impl FloorTile {
+    pub fn friction(&self) -> f32 {
+        match self {
+            FloorTile::Sand => 9.0,
+            FloorTile::BrownFloor => 12.0,
+            FloorTile::GrayFloor => 7.0, // Smooth metal plates
+            FloorTile::RainbowTiles => 8.0,
+            FloorTile::Ice => 0.8,
+            FloorTile::Lava => 12.0,
+        }
+    }
+}
+
 impl Tile {
     pub fn is_solid(&self) -> bool {
         match self {
@@ -63,12 +81,17 @@ impl Tile {
         }
     }
 
-    pub fn is_on_ice(&self) -> bool {
+    /// How quickly creatures walking on this tile can change their velocity, per second.
+    pub fn friction(&self) -> f32 {
         match self {
-            Tile::Open(FloorTile::Ice, _) => true,
-            _ => false,
+            Tile::Open(floor, _) => floor.friction(),
+            _ => FloorTile::BrownFloor.friction(),
         }
     }
+
+    pub fn is_lava(&self) -> bool {
+        matches!(self, Tile::Open(FloorTile::Lava, _))
+    }
 }
 
 #[derive(Resource)]
@@ -78,6 +101,15 @@ pub struct MapData {
     pub monster_map: Grid<bool>,
     pub player_pos: Transform,
     pub tile_map: Grid<Tile>,
+    pub room_centres: Vec<Coords>,
+    pub doors: Grid<Option<DoorRef>>,
+}
+
+/// Marks a tile as containing a door, so the AI can find and open it.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct DoorRef {
+    pub entity: Entity,
+    pub required_key: u8,
 }
 
 impl Default for MapData {
@@ -88,56 +120,64 @@ impl Default for MapData {
             monster_map: Grid::<bool>::new(1, 1),
             player_pos: Transform::IDENTITY,
             tile_map: Grid::<Tile>::new(1, 1),
+            room_centres: vec![],
+            doors: Grid::<Option<DoorRef>>::new(1, 1),
         }
     }
 }
 
 impl MapData {
     pub fn line_of_sight(&self, p0: Vec3, p1: Vec3) -> bool {
-        fn make_range(f0: f32, f1: f32) -> Option<(std::ops::Range<i32>, i32)> {
+        self.first_blocked(p0, p1).is_none()
+    }
+
+    /// Where the segment from `p0` to `p1` first crosses into a tile that blocks sight,
+    /// as a fraction of its length.
+    pub fn first_blocked(&self, p0: Vec3, p1: Vec3) -> Option<f32> {
+        /// The tile boundaries crossed along one axis in travel order, with the offset of the tile behind them.
+        fn crossings(f0: f32, f1: f32) -> impl Iterator<Item = (i32, i32)> {
             let i0 = f0.floor() as i32;
             let i1 = f1.floor() as i32;
 
-            if i0 == i1 {
-                None
+            let (forward, backward) = if i0 <= i1 {
+                ((i0 + 1)..(i1 + 1), 0..0)
             } else {
-                Some(if i0 < i1 {
-                    ((i0 + 1)..(i1 + 1), 0)
-                } else {
-                    ((i1 + 1)..(i0 + 1), -1)
-                })
-            }
+                (0..0, (i1 + 1)..(i0 + 1))
+            };
+            forward.map(|i| (i, 0)).chain(backward.rev().map(|i| (i, -1)))
         }
 
         let delta = p1 - p0;
+        let mut hit: Option<f32> = None;
+
         // Steps over X boundaries
-        if let Some((range, offset)) = make_range(p0.x, p1.x) {
-            // z = ax + b
-            let a = delta.z / delta.x;
-            let b = p0.z - a * p0.x;
-
-            for x in range {
-                let z = (a * (x as f32) + b) as i32;
-                if self.los_map[(x + offset, z)] {
-                    return false;
-                }
+        for (x, offset) in crossings(p0.x, p1.x) {
+            let t = (x as f32 - p0.x) / delta.x;
+            let z = (p0.z + t * delta.z) as i32;
+            if self.los_map[(x + offset, z)] {
+                hit = Some(t);
+                break;
             }
         }
 
-        // Steps over Z boundaries
-        if let Some((range, offset)) = make_range(p0.z, p1.z) {
-            // x = az + b
-            let a = delta.x / delta.z;
-            let b = p0.x - a * p0.z;
-
-            for z in range {
-                let x = (a * (z as f32) + b) as i32;
-                if self.los_map[(x, z + offset)] {
-                    return false;
-                }
+        // Steps over Z boundaries, up to the first X boundary that was blocked
+        for (z, offset) in crossings(p0.z, p1.z) {
+            let t = (z as f32 - p0.z) / delta.z;
+            if hit.is_some_and(|hit| t >= hit) {
+                break;
+            }
+            let x = (p0.x + t * delta.x) as i32;
+            if self.los_map[(x, z + offset)] {
+                hit = Some(t);
+                break;
             }
         }
-        true
+        hit
+    }
+
+    /// True if there is a closed door at the given position that doesn't need a key.
+    pub fn is_unlocked_door(&self, pos: Coords) -> bool {
+        self.solid_map[pos] && self.doors[pos].is_some_and(|door| door.required_key == 0)
     }
 
     pub fn can_see_player(&self, pos: Vec3, sight_radius: f32) -> bool {
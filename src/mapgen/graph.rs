@@ -76,6 +76,10 @@ impl<T> Graph<T> {
         }
     }
 
+    pub fn node_coords(&self) -> Vec<Coords> {
+        self.nodes.iter().map(|n| n.coords).collect()
+    }
+
     fn connect(&mut self, from: usize, to: usize) {
         self.nodes[from].edges.push(to);
         self.nodes[to].edges.push(from);
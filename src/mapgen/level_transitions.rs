@@ -9,9 +9,19 @@ pub fn add_level_transition_objects(
     rng: &mut fastrand::Rng,
     spawn_objects: &mut Vec<(Coords, SpawnObject)>,
     level: u8,
+    arena: Option<Coords>,
 ) {
     let items_to_spawn = choose_level_transition_items(rng, level);
-    spawn_object_instances(dist_map, rng, spawn_objects, items_to_spawn)
+    spawn_object_instances(dist_map, rng, spawn_objects, items_to_spawn);
+
+    if let Some(arena) = arena {
+        spawn_objects.push((arena, SpawnObject::Lich));
+    }
+}
+
+/// The last level has the lich instead of portals to the next level.
+pub fn is_boss_level(level: u8) -> bool {
+    level as usize >= crate::mapgen::style::BASE_LEVELS.len()
 }
 
 fn choose_level_transition_items(rng: &mut fastrand::Rng, level: u8) -> Vec<SpawnObject> {
@@ -40,10 +50,8 @@ fn choose_level_transition_items(rng: &mut fastrand::Rng, level: u8) -> Vec<Spaw
 
         portals
     } else {
-        // In the endboss level, add a phylactery and the lich.
+        // In the endboss level, hide the phylactery far away. The lich waits in its arena.
         vec![SpawnObject::Phylactery]
-
-        // TODO: Add lich
     }
 }
 
@@ -19,6 +19,7 @@ pub struct MapGenResult {
     pub tilemap: Grid<Tile>,
     pub player_pos: Coords,
     pub spawn_objects: Vec<(Coords, SpawnObject)>,
+    pub room_centres: Vec<Coords>,
 }
 #[derive(Copy, Clone)]
 pub enum RoomShape {
@@ -26,6 +27,8 @@ pub enum RoomShape {
     Constructed,
     Mirror,
     DoubleRect,
+    /// The room of the final boss fight
+    Arena,
 }
 
 pub fn make_map(level: u8, level_style: LevelStyle, rng: &mut fastrand::Rng) -> MapGenResult {
@@ -35,6 +38,12 @@ pub fn make_map(level: u8, level_style: LevelStyle, rng: &mut fastrand::Rng) ->
 
     let mut spawn_objects = vec![];
 
+    let arena = if level_transitions::is_boss_level(level) {
+        Some(add_arena(&mut map, &mut graph, level_style, rng))
+    } else {
+        None
+    };
+
     for _ in 0..50 {
         let style = *level_style.rooms().rand_front_loaded(rng);
         let metadata = rooms::RoomMetaData::new(style, rng);
@@ -57,10 +66,16 @@ pub fn make_map(level: u8, level_style: LevelStyle, rng: &mut fastrand::Rng) ->
         corridors::connect_rooms(&mut map, rng, edge, &mut spawn_objects);
     }
 
-    let player_pos = choose_pos(&map, rng);
+    let player_pos = choose_player_pos(&map, arena, rng);
     let (_dir_map, dist_map) = crate::grid::find_path4_to(&map, |tile| tile.is_solid(), player_pos);
 
-    level_transitions::add_level_transition_objects(&dist_map, rng, &mut spawn_objects, level);
+    level_transitions::add_level_transition_objects(
+        &dist_map,
+        rng,
+        &mut spawn_objects,
+        level,
+        arena,
+    );
 
     // And add some shops
     let shop_count = match level_style {
@@ -76,19 +91,57 @@ pub fn make_map(level: u8, level_style: LevelStyle, rng: &mut fastrand::Rng) ->
         spawn_objects.push((choose_pos(&map, rng), SpawnObject::Shop));
     }
 
+    let trap_count = match level_style {
+        LevelStyle::Castle => rng.i32(0..=2),
+        LevelStyle::Caves => 0,
+        LevelStyle::Sewers => rng.i32(1..=3),
+        LevelStyle::Machine => rng.i32(3..=6),
+        LevelStyle::Hell => rng.i32(1..=3),
+        LevelStyle::Ice => 0,
+    };
+
+    for _ in 0..trap_count {
+        if let Some(trap) = choose_trap_pos(&map, player_pos, rng) {
+            spawn_objects.push(trap);
+        }
+    }
+
     spawn_objects.retain(|(pos, obj)| obj.validate_pos(*pos, &map));
 
     if level_style == LevelStyle::Ice {
         add_ice(&mut map, rng);
     }
+    if level_style == LevelStyle::Hell {
+        add_lava(&mut map, rng, player_pos, &spawn_objects);
+    }
 
     MapGenResult {
         tilemap: map,
         player_pos,
         spawn_objects,
+        room_centres: graph.node_coords(),
     }
 }
 
+/// Places the boss arena at a random spot in the empty map and returns its centre.
+fn add_arena(
+    map: &mut Grid<Tile>,
+    graph: &mut graph::Graph<rooms::RoomMetaData>,
+    level_style: LevelStyle,
+    rng: &mut fastrand::Rng,
+) -> Coords {
+    let mut metadata = rooms::RoomMetaData::new(level_style.rooms()[0], rng);
+    metadata.shape = RoomShape::Arena;
+    let room = metadata.make_room(rng);
+
+    let transform = GridTransform::make_rand(map.size(), room.size(), rng);
+    check_place_room(map, &room, &transform).expect("The arena is placed in an empty map");
+
+    let centre = transform.map(room.size().rand_center(rng));
+    graph.add_node(centre, metadata);
+    centre
+}
+
 fn check_place_room(
     map: &mut Grid<Tile>,
     room: &Grid<Tile>,
@@ -141,6 +194,85 @@ fn choose_pos(map: &Grid<Tile>, rng: &mut fastrand::Rng) -> Coords {
     panic!("WTF");
 }
 
+/// Like `choose_pos`, but keeps the player out of the boss arena.
+fn choose_player_pos(map: &Grid<Tile>, arena: Option<Coords>, rng: &mut fastrand::Rng) -> Coords {
+    const MIN_ARENA_DIST: i32 = 12;
+    loop {
+        let pos = choose_pos(map, rng);
+        match arena {
+            Some(centre) if pos.eucledian_dist_sq(centre) < MIN_ARENA_DIST * MIN_ARENA_DIST => {}
+            _ => return pos,
+        }
+    }
+}
+
+/// Creatures should be safe from traps and lava this close to the player start.
+const SAFE_START_DIST: i32 = 5;
+
+/// Finds a wall next to an open tile, with enough room in front of it for the darts to fly.
+fn choose_trap_pos(
+    map: &Grid<Tile>,
+    player_pos: Coords,
+    rng: &mut fastrand::Rng,
+) -> Option<(Coords, SpawnObject)> {
+    const DIRS: [Coords; 4] = [
+        Coords::new(1, 0),
+        Coords::new(-1, 0),
+        Coords::new(0, 1),
+        Coords::new(0, -1),
+    ];
+
+    for _ in 0..256 {
+        let front = choose_pos(map, rng);
+        let dir = DIRS[rng.usize(0..DIRS.len())];
+        let pos = front - dir;
+
+        if !matches!(map[pos], Tile::Wall(_))
+            || front.eucledian_dist_sq(player_pos) < SAFE_START_DIST * SAFE_START_DIST
+        {
+            continue;
+        }
+
+        let free = (1..=3).all(|i| {
+            let c = pos + Coords::new(dir.x * i, dir.z * i);
+            map.contains_coord(c.x, c.z) && !map[c].is_solid()
+        });
+        if free {
+            return Some((pos, SpawnObject::DartTrap { dir }));
+        }
+    }
+    None
+}
+
+/// Adds pools of lava, but not near the player start or under objects.
+fn add_lava(
+    map: &mut Grid<Tile>,
+    rng: &mut fastrand::Rng,
+    player_pos: Coords,
+    spawn_objects: &[(Coords, SpawnObject)],
+) {
+    use noise::{NoiseFn, Perlin};
+    const SCALE: f64 = 6.0;
+
+    let perlin = Perlin::new(rng.u32(0..u32::MAX));
+
+    for (pos, tile) in map.iter_mut() {
+        if pos.eucledian_dist_sq(player_pos) < SAFE_START_DIST * SAFE_START_DIST
+            || spawn_objects.iter().any(|(p, _)| *p == pos)
+        {
+            continue;
+        }
+
+        if let Tile::Open(floor, _) = tile {
+            let val = perlin.get([pos.x as f64 / SCALE, pos.z as f64 / SCALE]);
+
+            if val > 0.45 {
+                *floor = FloorTile::Lava;
+            }
+        }
+    }
+}
+
 fn add_ice(map: &mut Grid<Tile>, rng: &mut fastrand::Rng) {
     use noise::{NoiseFn, Perlin};
     const SCALE: f64 = 10.0;
@@ -158,3 +290,30 @@ fn add_ice(map: &mut Grid<Tile>, rng: &mut fastrand::Rng) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boss_level() {
+        for seed in 0..10 {
+            let mut rng = fastrand::Rng::with_seed(seed);
+            let result = make_map(5, LevelStyle::Hell, &mut rng);
+
+            let objects = &result.spawn_objects;
+            let lich = objects.iter().find(|(_, o)| *o == SpawnObject::Lich);
+            let (arena, _) = lich.expect("The boss level should have a lich");
+            assert!(objects.iter().any(|(_, o)| *o == SpawnObject::Phylactery));
+            assert!(!result.tilemap[*arena].is_solid());
+            assert!(result.player_pos.eucledian_dist_sq(*arena) >= 12 * 12);
+        }
+
+        let mut rng = fastrand::Rng::with_seed(0);
+        let result = make_map(2, LevelStyle::Caves, &mut rng);
+        assert!(!result
+            .spawn_objects
+            .iter()
+            .any(|(_, o)| *o == SpawnObject::Lich));
+    }
+}
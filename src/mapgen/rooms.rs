@@ -33,6 +33,7 @@ impl RoomMetaData {
             }
             super::RoomShape::Mirror => self.make_mirror_floor(rng, 10..20),
             super::RoomShape::DoubleRect => self.make_doublerect_floor(rng, 5..14),
+            super::RoomShape::Arena => self.make_arena_floor(15, 13),
         };
 
         self.add_walls(&mut map);
@@ -152,6 +153,21 @@ impl RoomMetaData {
         map
     }
 
+    /// A large open room with four pillars to hide behind during the boss fight.
+    fn make_arena_floor(&self, x_max: i32, z_max: i32) -> Grid<Tile> {
+        let mut map = Grid::<Tile>::new(x_max + 2, z_max + 2);
+        for c in map.size().shrink(1).iter() {
+            map[c] = Tile::Open(self.floor, self.ceil);
+        }
+
+        let (x0, x1) = (x_max / 4 + 1, x_max - x_max / 4);
+        let (z0, z1) = (z_max / 4 + 1, z_max - z_max / 4);
+        for pillar in [(x0, z0), (x1, z0), (x0, z1), (x1, z1)] {
+            map[pillar] = Tile::Void;
+        }
+        map
+    }
+
     fn add_walls(&self, map: &mut Grid<Tile>) {
         let wall = self.wall;
         // Add walls
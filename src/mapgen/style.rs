@@ -1,7 +1,6 @@
-use crate::{
-    combat::MonsterType,
-    map::{CeilingTile, DoorType, FloorTile, WallTile},
-};
+use serde::{Deserialize, Serialize};
+
+use crate::map::{CeilingTile, DoorType, FloorTile, WallTile};
 
 use super::randitem::RandItem;
 
@@ -15,7 +14,7 @@ pub const BASE_LEVELS: [LevelStyle; 5] = [
 
 pub const ALT_LEVELS: [LevelStyle; 1] = [LevelStyle::Ice];
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
 pub enum LevelStyle {
     Castle,
     Caves,
@@ -80,19 +79,6 @@ impl LevelStyle {
         }
     }
 
-    pub fn monsters(self) -> &'static [MonsterType] {
-        use MonsterType::*;
-        match self {
-            Self::Castle => &[EyeMonster1, Goblin, Imp, Laima],
-            Self::Caves => &[EyeMonster1, Laima, Ettin, EyeMonster2, Goblin],
-            Self::Sewers => &[Laima, EyeMonster2, Goblin, EyeMonster1],
-            Self::Machine => &[IronGolem, EyeMonster2, Ettin],
-            Self::Hell => &[Imp, EyeMonster2, Demon, Ettin],
-
-            Self::Ice => &[Ettin, Goblin, Snowman, EyeMonster2],
-        }
-    }
-
     pub fn from_str(name: &str) -> Result<Self, String> {
         Ok(match name {
             "castle" => Self::Castle,
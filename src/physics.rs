@@ -1,18 +1,34 @@
 use bevy::{
-    prelude::{Commands, Component, Entity, Query, Res, Transform, Vec3},
+    prelude::{
+        Commands, Component, Entity, Event, EventWriter, Query, Res, ResMut, Resource, Transform,
+        Vec3, With, Without,
+    },
     time::Time,
 };
 
-use crate::map::MapData;
+use crate::{
+    combat::{projectile::Projectile, CreatureStats},
+    grid::{Coords, Grid},
+    map::MapData,
+};
+
+/// Colliders stop this far before walls
+const CONTACT_SKIN: f32 = 0.001;
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum MapCollisionEvent {
-    #[allow(dead_code)] // TODO: Remove after 0.2
     Bounce(f32),
     Destroy,
     Stop,
 }
 
+/// Sent when a movable hits a wall. With `MapCollisionEvent::Destroy`, the entity is already despawned.
+#[derive(Event)]
+pub struct WallHitEvent {
+    pub entity: Entity,
+    pub pos: Vec3,
+}
+
 #[derive(Component, Clone)]
 pub struct Collider {
     pub pos: Vec3,
@@ -37,36 +53,109 @@ impl Collider {
         self.pos.distance_squared(other.pos) <= xz_dist_squared
     }
 
-    // TODO: Better return type
-    fn collide_map(&self, grid_solid: &crate::grid::Grid<bool>) -> bool {
+    /// Distance along the ray from `origin` in the normalized direction `dir` to where it enters the collider.
+    /// A ray starting inside the collider hits it right away.
+    pub fn ray_hit(&self, origin: Vec3, dir: Vec3) -> Option<f32> {
+        let to_centre = self.pos - origin;
+        let closest = to_centre.dot(dir);
+        let miss_sq = to_centre.length_squared() - closest * closest;
+        let radius_sq = self.radius * self.radius;
+        if miss_sq > radius_sq {
+            return None;
+        }
+
+        let half_chord = (radius_sq - miss_sq).sqrt();
+        if closest + half_chord < 0.0 {
+            return None; // Behind the origin
+        }
+        Some((closest - half_chord).max(0.0))
+    }
+
+    /// Moves this collider out of `other` along the line between their centres, as far as the walls allow.
+    /// Only the XZ plane is considered, so creatures can't be stood on.
+    pub fn push_out(&mut self, other: &Self, grid_solid: &Grid<bool>) {
+        let offset = Vec3::new(self.pos.x - other.pos.x, 0.0, self.pos.z - other.pos.z);
+        let min_dist = self.radius + other.radius;
+        let dist = offset.length();
+        if dist >= min_dist {
+            return;
+        }
+
+        // Exactly on top of each other, so any direction will do
+        let normal = if dist > f32::EPSILON {
+            offset / dist
+        } else {
+            Vec3::X
+        };
+        let push = normal * (min_dist - dist);
+        self.pos += push * self.sweep(push, grid_solid);
+    }
+
+    /// When moving by `delta`, the fraction of `delta` where this collider starts touching `other`.
+    pub fn sweep_other(&self, delta: Vec3, other: &Self) -> Option<f32> {
+        let length = delta.length();
+        if length <= f32::EPSILON {
+            return self.collide_other(other).then_some(0.0);
+        }
+
+        let grown = Self::new(other.pos, self.radius + other.radius);
+        let dist = grown.ray_hit(self.pos, delta / length)?;
+        (dist <= length).then_some(dist / length)
+    }
+
+    /// When moving by `delta`, the fraction of `delta` the collider can travel before it touches a solid tile.
+    /// Every tile along the way is checked, so fast colliders can't skip through thin walls or corners.
+    /// Tiles the collider already overlaps are ignored, so it can get out of them.
+    pub fn sweep(&self, delta: Vec3, grid_solid: &Grid<bool>) -> f32 {
         /*
         TODO: Ceiling and floor heights
         let floor_height = 0.0;
         let ceil_height = 1.0;
+        */
 
-        if pos.y - radius < floor_height {
-            return true;
-        }
-
-        if pos.y + radius > ceil_height {
-            return true;
+        /// When `p` moving by `d` is within `lo..hi`, as fractions of `d`.
+        fn slab(p: f32, d: f32, lo: f32, hi: f32) -> Option<(f32, f32)> {
+            if d == 0.0 {
+                return (lo <= p && p < hi).then_some((f32::NEG_INFINITY, f32::INFINITY));
+            }
+            let t0 = (lo - p) / d;
+            let t1 = (hi - p) / d;
+            Some((t0.min(t1), t0.max(t1)))
         }
-        */
 
-        let x0 = f32::floor(self.pos.x - self.radius) as i32;
-        let x1 = f32::floor(self.pos.x + self.radius) as i32;
-        let z0 = f32::floor(self.pos.z - self.radius) as i32;
-        let z1 = f32::floor(self.pos.z + self.radius) as i32;
+        let r = self.radius;
+        let end = self.pos + delta;
+        let x0 = (self.pos.x.min(end.x) - r).floor() as i32;
+        let x1 = (self.pos.x.max(end.x) + r).floor() as i32;
+        let z0 = (self.pos.z.min(end.z) - r).floor() as i32;
+        let z1 = (self.pos.z.max(end.z) + r).floor() as i32;
 
+        let mut hit = 1.0f32;
         for z in z0..=z1 {
             for x in x0..=x1 {
-                if grid_solid[(x, z)] {
-                    return true;
+                if grid_solid.contains_coord(x, z) && !grid_solid[(x, z)] {
+                    continue;
+                }
+
+                // The collider touches the tile while its centre is in the tile grown by its radius
+                let (x, z) = (x as f32, z as f32);
+                let Some((x_enter, x_exit)) = slab(self.pos.x, delta.x, x - r, x + 1.0 + r) else {continue;};
+                let Some((z_enter, z_exit)) = slab(self.pos.z, delta.z, z - r, z + 1.0 + r) else {continue;};
+                let enter = x_enter.max(z_enter);
+                let exit = x_exit.min(z_exit);
+
+                if enter < exit && enter >= 0.0 && enter < hit {
+                    hit = enter;
                 }
             }
         }
 
-        false
+        if hit < 1.0 {
+            // Stop just short of the wall, since touching it already counts as overlapping it
+            let length = Vec3::new(delta.x, 0.0, delta.z).length();
+            hit = (hit - CONTACT_SKIN / length).max(0.0);
+        }
+        hit
     }
 }
 
@@ -74,6 +163,8 @@ impl Collider {
 pub struct PhysicsMovable {
     pub velocity: Vec3,
     pub on_hit_wall: MapCollisionEvent,
+    /// How far the last physics step moved the collider, so collision checks can sweep along the path
+    pub last_move: Vec3,
 }
 
 impl PhysicsMovable {
@@ -81,6 +172,7 @@ impl PhysicsMovable {
         Self {
             velocity,
             on_hit_wall,
+            last_move: Vec3::ZERO,
         }
     }
 
@@ -94,34 +186,150 @@ impl PhysicsMovable {
         }
     }
 
+    /// Moves the collider by `velocity * dt`, up to the first wall. Returns the contact point when it hit one.
+    /// With `MapCollisionEvent::Destroy`, the collider stays at the contact point.
+    fn step(&mut self, pb: &mut Collider, dt: f32, map: &MapData) -> Option<Vec3> {
+        let start = pb.pos;
+        let delta = self.velocity * dt;
+        let t = pb.sweep(delta, &map.solid_map);
+        pb.pos += delta * t;
+
+        let contact = (t < 1.0).then_some(pb.pos);
+        if contact.is_some() && self.on_hit_wall != MapCollisionEvent::Destroy {
+            self.move_bounce(pb, dt * (1.0 - t), map);
+        }
+
+        self.last_move = pb.pos - start;
+        contact
+    }
+
     fn move_bounce(&mut self, pb: &mut Collider, dt: f32, map: &MapData) {
         let mut new_velocity = Vec3::ZERO;
 
         for axis in self.velocity_axis() {
-            let new_pos = pb.pos + (axis * dt);
-            if !pb.with_pos(new_pos).collide_map(&map.solid_map) {
-                pb.pos = new_pos;
+            let delta = axis * dt;
+            let t = pb.sweep(delta, &map.solid_map);
+            pb.pos += delta * t;
+            if t >= 1.0 {
                 new_velocity += axis;
                 continue;
             }
             if let MapCollisionEvent::Bounce(bounce) = self.on_hit_wall {
-                let new_pos = pb.pos + (axis * dt * -bounce);
-                if !pb.with_pos(new_pos).collide_map(&map.solid_map) {
-                    pb.pos = new_pos;
-                    new_velocity += axis;
-                    continue;
-                }
+                let back = -delta * bounce * (1.0 - t);
+                pb.pos += back * pb.sweep(back, &map.solid_map);
+                new_velocity -= axis * bounce;
             }
         }
         self.velocity = new_velocity;
     }
 }
 
+/// Colliders bucketed by the tile their centre is in, so collision checks only have to look at the tiles around them.
+/// It is rebuilt every frame after the physics moved things.
+#[derive(Resource)]
+pub struct SpatialIndex {
+    cells: Grid<Vec<(Entity, Collider)>>,
+    /// Only these cells have to be emptied by `clear`
+    used: Vec<Coords>,
+    /// Colliders reach this far beyond the tile their centre is in
+    max_radius: f32,
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        Self::new(1, 1)
+    }
+}
+
+impl SpatialIndex {
+    pub fn new(x_max: i32, z_max: i32) -> Self {
+        Self {
+            cells: Grid::new(x_max, z_max),
+            used: vec![],
+            max_radius: 0.0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        // Keep the buckets, to avoid allocating them again next frame
+        for coords in self.used.drain(..) {
+            self.cells[coords].clear();
+        }
+        self.max_radius = 0.0;
+    }
+
+    /// Colliders outside of the map are ignored.
+    pub fn insert(&mut self, entity: Entity, collider: &Collider) {
+        let coords = Coords::from_vec(collider.pos);
+        if !self.cells.contains_coord(coords.x, coords.z) {
+            return;
+        }
+
+        let cell = &mut self.cells[coords];
+        if cell.is_empty() {
+            self.used.push(coords);
+        }
+        cell.push((entity, collider.clone()));
+        self.max_radius = self.max_radius.max(collider.radius);
+    }
+
+    /// All colliders that might touch the circle around `pos`. Callers still have to check for the actual overlap.
+    pub fn nearby(&self, pos: Vec3, radius: f32) -> impl Iterator<Item = (Entity, &Collider)> {
+        let reach = Vec3::splat(radius + self.max_radius);
+        let min = Coords::from_vec(pos - reach);
+        let max = Coords::from_vec(pos + reach);
+        let (x0, x1) = (min.x.max(0), max.x.min(self.cells.x_max() - 1));
+        let (z0, z1) = (min.z.max(0), max.z.min(self.cells.z_max() - 1));
+
+        (z0..=z1)
+            .flat_map(move |z| (x0..=x1).map(move |x| &self.cells[(x, z)]))
+            .flatten()
+            .map(|(entity, collider)| (*entity, collider))
+    }
+}
+
+/// Projectiles are left out, since nothing is looking for them.
+pub fn update_spatial_index(
+    mut index: ResMut<SpatialIndex>,
+    map: Res<MapData>,
+    query: Query<(Entity, &Collider), Without<Projectile>>,
+) {
+    let size = map.solid_map.size();
+    if index.cells.size() != size {
+        *index = SpatialIndex::new(size.p1.x, size.p1.z);
+    }
+
+    index.clear();
+    for (entity, collider) in query.iter() {
+        index.insert(entity, collider);
+    }
+}
+
+/// Creatures moved by the physics, like the player, can't walk through the creatures that walk from tile to tile.
+/// Pushing them out sideways lets them slide around each other.
+#[allow(clippy::type_complexity)] // Not really applicable for bevy systems
+pub fn push_out_of_creatures(
+    map: Res<MapData>,
+    mut mover_query: Query<
+        (&mut Collider, &mut Transform),
+        (With<PhysicsMovable>, With<CreatureStats>),
+    >,
+    obstacle_query: Query<&Collider, (With<CreatureStats>, Without<PhysicsMovable>)>,
+) {
+    for (mut pb, mut transform) in mover_query.iter_mut() {
+        for obstacle in obstacle_query.iter() {
+            pb.push_out(obstacle, &map.solid_map);
+        }
+        transform.translation = pb.pos;
+    }
+}
+
 pub fn do_physics(
     mut commands: Commands,
     time: Res<Time>,
     map: Res<MapData>,
     mut query: Query<(Entity, &mut Transform, &mut PhysicsMovable, &mut Collider)>,
+    mut ev_wall_hit: EventWriter<WallHitEvent>,
 ) {
     let dt = time.delta_seconds();
     for (entity, mut transform, mut movable, mut pb) in query.iter_mut() {
@@ -129,20 +337,238 @@ pub fn do_physics(
             continue;
         }
 
-        let delta = movable.velocity * dt;
+        if let Some(contact) = movable.step(&mut pb, dt, &map) {
+            ev_wall_hit.send(WallHitEvent {
+                entity,
+                pos: contact,
+            });
+            if movable.on_hit_wall == MapCollisionEvent::Destroy {
+                commands.entity(entity).despawn();
+            }
+        }
 
-        let new_pos = transform.translation + delta;
-        if !pb.with_pos(new_pos).collide_map(&map.solid_map) {
-            pb.pos = new_pos;
-        } else {
-            match movable.on_hit_wall {
-                MapCollisionEvent::Bounce(_) | MapCollisionEvent::Stop => {
-                    movable.move_bounce(&mut pb, dt, &map);
+        transform.translation = pb.pos;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walls are '#' and everything else is open.
+    fn make_map(rows: &[&str]) -> MapData {
+        let mut solid_map = Grid::new(rows[0].len() as i32, rows.len() as i32);
+        for (z, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                solid_map[(x as i32, z as i32)] = c == '#';
+            }
+        }
+        MapData {
+            solid_map,
+            ..Default::default()
+        }
+    }
+
+    /// Steps a collider once with a huge time step, as with a very low frame rate.
+    fn big_step(
+        map: &MapData,
+        from: Vec3,
+        velocity: Vec3,
+        on_hit_wall: MapCollisionEvent,
+    ) -> (Collider, PhysicsMovable, Option<Vec3>) {
+        let mut pb = Collider::new(from, 0.1);
+        let mut movable = PhysicsMovable::new(velocity, on_hit_wall);
+        let contact = movable.step(&mut pb, 0.5, map);
+        (pb, movable, contact)
+    }
+
+    #[test]
+    fn no_tunneling_through_thin_wall() {
+        let map = make_map(&["##########", "#....#...#", "##########"]);
+        let from = Vec3::new(1.5, 0.5, 1.5);
+        for speed in [10.0, 40.0, 100.0] {
+            let (pb, _, contact) = big_step(&map, from, Vec3::X * speed, MapCollisionEvent::Stop);
+            let contact = contact.expect("The wall should be hit");
+            assert!((contact.x - 4.9).abs() < 0.01, "{contact:?}");
+            assert!(pb.pos.x < 4.9);
+        }
+
+        // Also when coming from the other side
+        let from = Vec3::new(8.5, 0.5, 1.5);
+        let (pb, _, contact) = big_step(&map, from, Vec3::NEG_X * 40.0, MapCollisionEvent::Destroy);
+        assert!((contact.unwrap().x - 6.1).abs() < 0.01);
+        assert_eq!(pb.pos, contact.unwrap());
+    }
+
+    #[test]
+    fn no_cutting_corners() {
+        let map = make_map(&[
+            "########", "#......#", "#......#", "#..#...#", "#......#", "#......#", "########",
+        ]);
+
+        // The path only grazes the corner of the pillar at (3, 3) and ends on a free tile
+        let from = Vec3::new(1.2, 0.5, 5.2);
+        let velocity = Vec3::new(8.0, 0.0, -8.0);
+        assert!(!map.solid_map[Coords::from_vec(from + velocity * 0.5)]);
+
+        let (_, _, contact) = big_step(&map, from, velocity, MapCollisionEvent::Destroy);
+        let contact = contact.expect("The corner should be hit");
+        assert!((contact.x - 2.9).abs() < 0.01, "{contact:?}");
+    }
+
+    #[test]
+    fn slide_and_bounce() {
+        let map = make_map(&["#######", "#.....#", "#.....#", "#######"]);
+
+        // Sliding along the wall keeps the velocity along it
+        let from = Vec3::new(1.5, 0.5, 1.5);
+        let velocity = Vec3::new(4.0, 0.0, -1.0);
+        let (pb, movable, _) = big_step(&map, from, velocity, MapCollisionEvent::Stop);
+        assert!(pb.pos.x > 3.0 && pb.pos.z > 1.09, "{:?}", pb.pos);
+        assert_eq!(movable.velocity, Vec3::X * 4.0);
+
+        let bounce = MapCollisionEvent::Bounce(1.0);
+        let (pb, movable, contact) = big_step(&map, from, Vec3::X * 20.0, bounce);
+        assert!(contact.is_some());
+        assert_eq!(movable.velocity, Vec3::NEG_X * 20.0);
+        assert!(pb.pos.x > 1.0 && pb.pos.x < 5.9, "{:?}", pb.pos);
+    }
+
+    #[test]
+    fn push_out_of_creature() {
+        let map = make_map(&["#####", "#...#", "#...#", "#...#", "#####"]);
+        let monster = Collider::new(Vec3::new(2.5, 0.5, 2.5), 0.5);
+
+        // Walking into the monster off-centre pushes the player sideways, so it slides around
+        let mut player = Collider::new(Vec3::new(2.2, 0.5, 2.0), 0.125);
+        player.push_out(&monster, &map.solid_map);
+        assert!((player.pos.distance(monster.pos) - 0.625).abs() < 1e-4);
+        assert!(player.pos.x < 2.2 && player.pos.z < 2.0);
+
+        // But not into the wall
+        let mut player = Collider::new(Vec3::new(1.3, 0.5, 2.5), 0.125);
+        let monster = Collider::new(Vec3::new(1.6, 0.5, 2.5), 0.5);
+        player.push_out(&monster, &map.solid_map);
+        assert!((player.pos.x - 1.125).abs() < 0.01, "{:?}", player.pos);
+    }
+
+    #[test]
+    fn sweep_other_creature() {
+        let projectile = Collider::new(Vec3::new(0.0, 0.5, 0.0), 0.1);
+        let creature = Collider::new(Vec3::new(2.0, 0.5, 0.0), 0.4);
+
+        // Flies through the creature in a single step
+        let t = projectile.sweep_other(Vec3::X * 4.0, &creature).unwrap();
+        assert!((t - 0.375).abs() < 1e-4);
+
+        // Too short, or passing by
+        assert_eq!(projectile.sweep_other(Vec3::X, &creature), None);
+        let past = Vec3::new(4.0, 0.0, 2.0);
+        assert_eq!(projectile.sweep_other(past, &creature), None);
+        assert_eq!(projectile.sweep_other(Vec3::NEG_X * 4.0, &creature), None);
+    }
+
+    /// `count` colliders spread over a `size` x `size` map, like projectiles and creatures in a busy fight.
+    fn scatter(
+        ids: std::ops::Range<u32>,
+        size: f32,
+        radius: f32,
+        rng: &mut fastrand::Rng,
+    ) -> Vec<(Entity, Collider)> {
+        ids.map(|i| {
+            let pos = Vec3::new(rng.f32() * size, 0.5, rng.f32() * size);
+            (Entity::from_raw(i), Collider::new(pos, radius))
+        })
+        .collect()
+    }
+
+    fn make_index(colliders: &[(Entity, Collider)]) -> SpatialIndex {
+        let mut index = SpatialIndex::new(64, 64);
+        for (entity, collider) in colliders {
+            index.insert(*entity, collider);
+        }
+        index
+    }
+
+    /// The overlapping pairs, found by testing every pair and with the index.
+    fn find_hits(
+        projectiles: &[(Entity, Collider)],
+        creatures: &[(Entity, Collider)],
+        index: Option<&SpatialIndex>,
+    ) -> Vec<(Entity, Entity)> {
+        let mut hits = vec![];
+        for (projectile, body) in projectiles {
+            let mut check = |creature: Entity, target: &Collider| {
+                if body.collide_other(target) {
+                    hits.push((*projectile, creature));
                 }
-                MapCollisionEvent::Destroy => commands.entity(entity).despawn(),
+            };
+            match index {
+                Some(index) => index
+                    .nearby(body.pos, body.radius)
+                    .for_each(|(creature, target)| check(creature, target)),
+                None => creatures
+                    .iter()
+                    .for_each(|(creature, target)| check(*creature, target)),
             }
         }
+        hits.sort();
+        hits
+    }
 
-        transform.translation = pb.pos;
+    #[test]
+    fn nearby_matches_brute_force() {
+        let mut rng = fastrand::Rng::with_seed(3);
+        let projectiles = scatter(0..300, 16.0, 0.1, &mut rng);
+        let mut creatures = scatter(1000..1060, 16.0, 0.5, &mut rng);
+        let big = Collider::new(Vec3::new(8.0, 0.5, 8.0), 2.5);
+        creatures.push((Entity::from_raw(2000), big));
+        let index = make_index(&creatures);
+
+        let hits = find_hits(&projectiles, &creatures, None);
+        assert!(!hits.is_empty());
+        assert_eq!(find_hits(&projectiles, &creatures, Some(&index)), hits);
+    }
+
+    #[test]
+    fn clear_keeps_nothing() {
+        let mut rng = fastrand::Rng::with_seed(5);
+        let mut index = make_index(&scatter(0..50, 8.0, 0.5, &mut rng));
+        index.clear();
+        assert_eq!(index.nearby(Vec3::new(4.0, 0.5, 4.0), 8.0).count(), 0);
+    }
+
+    /// Run with `cargo test --release spatial_index_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn spatial_index_benchmark() {
+        const FRAMES: u32 = 100;
+        let mut rng = fastrand::Rng::with_seed(7);
+
+        for (projectile_count, creature_count) in [(100, 30), (500, 60), (2000, 100)] {
+            let projectiles = scatter(0..projectile_count, 48.0, 0.1, &mut rng);
+            let creatures = scatter(10000..10000 + creature_count, 48.0, 0.5, &mut rng);
+
+            let start = std::time::Instant::now();
+            for _ in 0..FRAMES {
+                std::hint::black_box(find_hits(&projectiles, &creatures, None));
+            }
+            let brute_force = start.elapsed() / FRAMES;
+
+            let start = std::time::Instant::now();
+            let mut index = SpatialIndex::new(48, 48);
+            for _ in 0..FRAMES {
+                index.clear();
+                for (entity, collider) in &creatures {
+                    index.insert(*entity, collider);
+                }
+                std::hint::black_box(find_hits(&projectiles, &creatures, Some(&index)));
+            }
+            let indexed = start.elapsed() / FRAMES;
+
+            println!(
+                "{projectile_count} projectiles, {creature_count} creatures: brute force {brute_force:?}, indexed {indexed:?} per frame"
+            );
+        }
     }
 }
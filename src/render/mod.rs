@@ -74,10 +74,16 @@ impl RenderResource {
             }
         }
 
+        let positions_len = positions.len();
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
         mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uv0);
 
+        if let Some(color) = key.tint.color() {
+            let colors = vec![color; positions_len];
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        }
+
         if key.two_sided {
             mesh.set_indices(Some(Indices::U32(vec![0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7])));
         } else {
@@ -116,6 +122,7 @@ pub fn face_camera(
 pub struct Animation {
     frames: SpriteSeq, // indices of all the frames in the animation
     timer: Timer,
+    looping: bool,
 }
 
 impl Animation {
@@ -123,6 +130,15 @@ impl Animation {
         Self {
             frames,
             timer: Timer::from_seconds(anim_speed, TimerMode::Repeating),
+            looping: true,
+        }
+    }
+
+    /// Plays the frames once and then keeps showing the last frame.
+    pub fn once(frames: SpriteSeq, anim_speed: f32) -> Self {
+        Self {
+            looping: false,
+            ..Self::new(frames, anim_speed)
         }
     }
 
@@ -130,8 +146,13 @@ impl Animation {
         let mut x = sprite.tile.x;
 
         x += 1;
-        if x == self.frames.x.end {
-            x = self.frames.x.start;
+        if !self.frames.x.contains(&x) {
+            // Sprites that come from another animation start at the first frame
+            x = if self.looping || !self.frames.x.contains(&sprite.tile.x) {
+                self.frames.x.start
+            } else {
+                self.frames.x.end - 1
+            };
         };
 
         let tile = spritemap::SpritePos {
@@ -144,6 +165,7 @@ impl Animation {
             flipped: false,
             two_sided: false,
             tile,
+            tint: sprite.tint,
         }
     }
 }
@@ -156,6 +178,28 @@ pub struct Sprite3d {
     pub tile: spritemap::SpritePos,
     pub flipped: bool,
     pub two_sided: bool,
+    pub tint: Tint,
+}
+
+/// Colors the sprite, e.g. to show status effects. Tinted sprites get their own mesh with vertex colors.
+#[derive(Default, Hash, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Tint {
+    #[default]
+    None,
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl Tint {
+    fn color(&self) -> Option<[f32; 4]> {
+        match self {
+            Tint::None => None,
+            Tint::Red => Some([1.0, 0.5, 0.4, 1.0]),
+            Tint::Blue => Some([0.5, 0.7, 1.0, 1.0]),
+            Tint::Yellow => Some([1.0, 1.0, 0.4, 1.0]),
+        }
+    }
 }
 
 impl Sprite3d {
@@ -164,6 +208,7 @@ impl Sprite3d {
             tile,
             flipped: false,
             two_sided: false,
+            tint: Tint::None,
         }
     }
 
@@ -185,6 +230,7 @@ impl Sprite3d {
                 tile: self.tile,
                 flipped: false,
                 two_sided: false,
+                tint: self.tint,
             },
             pbr: PbrBundle {
                 mesh: render_res.get_mesh(self, meshes),
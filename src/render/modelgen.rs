@@ -128,6 +128,7 @@ pub fn floor_tex_id(tile: FloorTile, sprite_map: &SpriteMap) -> SpriteSeq {
         FloorTile::GrayFloor => "temple_gray_floor.png",
         FloorTile::RainbowTiles => "rainbow_tiles.png",
         FloorTile::Ice => "floor_ice.png",
+        FloorTile::Lava => "floor_lava.png",
     };
     sprite_map.get_block(str)
 }
@@ -2,7 +2,7 @@ use std::ops::Range;
 
 use bevy::{prelude::*, utils::HashMap};
 
-use super::{FaceCamera, Sprite3d, Sprite3dBundle};
+use super::{FaceCamera, Sprite3d, Sprite3dBundle, Tint};
 
 pub type USprite = u8;
 pub const TILESET_SIZE: u32 = 1024;
@@ -112,6 +112,7 @@ impl SpriteSeq {
             tile,
             flipped: false,
             two_sided: false,
+            tint: Tint::None,
         };
 
         Sprite3dBundle {
@@ -0,0 +1,254 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::player::{InputAction, InputState},
+    game::GameState,
+    ui::menus::{MenuInfo, OnClick},
+    CommandLineArgs, GameSettings,
+};
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(Recording::default())
+            .add_systems(Startup, load_replay)
+            .add_systems(OnExit(GameState::MainMenu), start_recording)
+            .add_systems(OnEnter(GameState::MainMenu), save_recording)
+            .add_systems(
+                FixedUpdate,
+                (
+                    play_tick
+                        .before(crate::combat::player::send_tick_input)
+                        .run_if(not(live_input)),
+                    record_tick.after(crate::combat::player::send_tick_input),
+                )
+                    .in_set(crate::game::TickSet::Input),
+            )
+            .add_systems(
+                Update,
+                (
+                    start_playback.run_if(in_state(GameState::MainMenu)),
+                    play_clicks.run_if(in_state(GameState::GameMenu)),
+                    record_clicks.after(crate::ui::menus::button_press),
+                ),
+            );
+    }
+}
+
+/// Everything needed to play a run again: the settings it started with, and the input of every tick.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Replay {
+    pub settings: GameSettings,
+    pub ticks: Vec<TickRecord>,
+    /// Menu buttons pressed during the run, with the number of ticks played before each
+    pub clicks: Vec<(usize, OnClick)>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+pub struct TickRecord {
+    /// Where the player was looking. It isn't an action, since the mouse turns the view by any amount.
+    pub yaw: f32,
+    pub actions: Vec<InputAction>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    IO(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Write(ron::Error),
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IO(err) => write!(f, "Could not access the replay file: {}", err),
+            Self::Parse(err) => write!(f, "Could not parse the replay: {}", err),
+            Self::Write(err) => write!(f, "Could not write the replay: {}", err),
+        }
+    }
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let text = std::fs::read_to_string(path).map_err(ReplayError::IO)?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ReplayError> {
+        ron::from_str(text).map_err(ReplayError::Parse)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        let text = ron::to_string(self).map_err(ReplayError::Write)?;
+        std::fs::write(path, text).map_err(ReplayError::IO)
+    }
+}
+
+/// The run that is being played, which can be saved with `--record`.
+#[derive(Resource, Default)]
+pub struct Recording(pub Replay);
+
+/// A replay loaded with `--replay`. Its input is used instead of the live input until it runs out, after which the
+/// player takes over.
+#[derive(Resource)]
+pub struct Playback {
+    replay: Replay,
+    started: bool,
+    tick: usize,
+    click: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            started: false,
+            tick: 0,
+            click: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick >= self.replay.ticks.len()
+    }
+}
+
+/// Whether the player controls the game, rather than a replay.
+pub fn live_input(playback: Option<Res<Playback>>) -> bool {
+    playback.is_none_or(|p| p.is_finished())
+}
+
+fn load_replay(mut commands: Commands, cl_args: Res<CommandLineArgs>) {
+    let Some(path) = &cl_args.replay else {return;};
+    match Replay::load(path) {
+        Ok(replay) => commands.insert_resource(Playback::new(replay)),
+        Err(err) => panic!("Loading the replay failed. {}", err),
+    }
+}
+
+/// Starts the run of the replay, once the tile set is ready.
+fn start_playback(
+    playback: Option<ResMut<Playback>>,
+    render_res: Res<crate::render::RenderResource>,
+    mut game_settings: ResMut<GameSettings>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut menu_info: ResMut<MenuInfo>,
+) {
+    let Some(mut playback) = playback else {return;};
+    if playback.started || render_res.material == Handle::default() {
+        return;
+    }
+    playback.started = true;
+
+    *game_settings = playback.replay.settings.clone();
+    game_state.set(GameState::InGame);
+    menu_info.unset();
+}
+
+fn play_tick(
+    mut playback: ResMut<Playback>,
+    mut state: ResMut<InputState>,
+    mut acts: EventWriter<InputAction>,
+) {
+    let playback = playback.as_mut();
+    let Some(tick) = playback.replay.ticks.get(playback.tick) else {return;};
+
+    state.yaw = tick.yaw;
+    acts.send_batch(tick.actions.iter().copied());
+    playback.tick += 1;
+}
+
+/// Presses the menu buttons of the replay, one per frame, when the run gets to them.
+fn play_clicks(playback: Option<ResMut<Playback>>, mut clicks: EventWriter<OnClick>) {
+    let Some(mut playback) = playback else {return;};
+    let Some((tick, click)) = playback.replay.clicks.get(playback.click).copied() else {return;};
+
+    if tick <= playback.tick {
+        clicks.send(click);
+        playback.click += 1;
+    }
+}
+
+/// Starts recording a new run. Runs without a seed get a random one here, so all their levels can be replayed.
+pub fn start_recording(mut game_settings: ResMut<GameSettings>, mut recording: ResMut<Recording>) {
+    game_settings
+        .map_seed
+        .get_or_insert_with(|| fastrand::u64(..));
+    recording.0 = Replay {
+        settings: game_settings.clone(),
+        ..default()
+    };
+}
+
+fn record_tick(
+    mut acts: EventReader<InputAction>,
+    state: Res<InputState>,
+    mut recording: ResMut<Recording>,
+) {
+    recording.0.ticks.push(TickRecord {
+        yaw: state.yaw,
+        actions: acts.read().copied().collect(),
+    });
+}
+
+fn record_clicks(mut clicks: EventReader<OnClick>, mut recording: ResMut<Recording>) {
+    let replay = &mut recording.0;
+    for click in clicks.read() {
+        replay.clicks.push((replay.ticks.len(), *click));
+    }
+}
+
+/// Writes the finished run to the file given with `--record`.
+fn save_recording(recording: Res<Recording>, cl_args: Res<CommandLineArgs>) {
+    let Some(path) = &cl_args.record else {return;};
+    if recording.0.ticks.is_empty() {
+        return; // No run was played yet
+    }
+
+    match recording.0.save(path) {
+        Ok(()) => println!("Saved the replay to {}", path.display()),
+        Err(err) => warn!("{}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let replay = Replay {
+            settings: GameSettings {
+                map_seed: Some(1234),
+                difficulty: 1.5,
+                daily: false,
+                infighting: true,
+            },
+            ticks: vec![
+                TickRecord {
+                    yaw: 0.5,
+                    actions: vec![InputAction::Forward, InputAction::Fire],
+                },
+                TickRecord {
+                    yaw: 0.25,
+                    actions: vec![InputAction::Move {
+                        right: 0.1,
+                        forward: -1.0,
+                    }],
+                },
+            ],
+            clicks: vec![(2, OnClick::Resume)],
+        };
+
+        let text = ron::to_string(&replay).unwrap();
+        let parsed = Replay::parse(&text).unwrap();
+        assert_eq!(parsed.settings.map_seed, Some(1234));
+        assert_eq!(parsed.settings.difficulty, 1.5);
+        assert!(parsed.ticks == replay.ticks);
+        assert!(matches!(parsed.clicks[..], [(2, OnClick::Resume)]));
+    }
+}
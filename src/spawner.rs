@@ -1,6 +1,9 @@
 use bevy::{
     asset::Assets,
-    ecs::system::{Commands, ResMut},
+    ecs::{
+        entity::Entity,
+        system::{Commands, Res, ResMut},
+    },
     math::Vec3,
     pbr::PbrBundle,
     render::mesh::Mesh,
@@ -8,23 +11,34 @@ use bevy::{
 };
 
 use crate::{
-    combat::{ai::AiMover, MonsterType},
+    combat::{
+        ai::AiMover,
+        boss::{Boss, Phylactery},
+        hazard::DartTrap,
+        monsterdefs::MonsterDefs,
+        status::StatusEffects,
+        weapondefs::WeaponDefs,
+        MonsterType,
+    },
     grid::Coords,
     interactable::{Door, Interactable},
     items::pickup::Pickup,
+    map::DoorRef,
     physics::Collider,
     render::{FaceCamera, Sprite3d},
     spawnobject::SpawnObject,
 };
 
-pub struct Spawner<'c1, 'c2, 'ma, 'me, 'r> {
+pub struct Spawner<'c1, 'c2, 'ma, 'me, 'r, 'md, 'wd> {
     pub commands: Commands<'c1, 'c2>,
     pub map_data: ResMut<'ma, crate::map::MapData>,
     pub meshes: ResMut<'me, Assets<Mesh>>,
     pub render_res: ResMut<'r, crate::render::RenderResource>,
+    pub monster_defs: Res<'md, MonsterDefs>,
+    pub weapon_defs: Res<'wd, WeaponDefs>,
 }
 
-impl Spawner<'_, '_, '_, '_, '_> {
+impl Spawner<'_, '_, '_, '_, '_, '_, '_> {
     // ---- ITEMS ----
     pub fn try_spawn_item(&mut self, item: Pickup, rng: &mut fastrand::Rng) -> bool {
         match self.choose_item_pos(rng) {
@@ -40,10 +54,15 @@ impl Spawner<'_, '_, '_, '_, '_> {
     }
 
     pub fn spawn_item_at_pos(&mut self, pos: Coords, item: Pickup) {
+        self.spawn_item_at(pos.to_vec(0.0), item);
+    }
+
+    /// Spawns an item on the floor at the given position. The height of `pos` is ignored.
+    pub fn spawn_item_at(&mut self, pos: Vec3, item: Pickup) {
         let uv = item.make_sprite(&self.render_res.sprites);
 
         let size = uv.tile.scale.game_size();
-        let pos = pos.to_vec(size * 0.5);
+        let pos = Vec3::new(pos.x, size * 0.5, pos.z);
 
         self.commands
             .spawn(uv.to_sprite_bundle(pos, &mut self.meshes, &mut self.render_res))
@@ -77,7 +96,10 @@ impl Spawner<'_, '_, '_, '_, '_> {
 
     pub fn try_spawn_monster(&mut self, monster: MonsterType, rng: &mut fastrand::Rng) -> bool {
         let Ok(pos) = self.choose_monster_pos(rng) else {
-            println!("Failed top spawn monster: {:?}", monster);
+            println!(
+                "Failed top spawn monster: {}",
+                self.monster_defs.get(monster).name
+            );
             return false;
         };
 
@@ -90,19 +112,39 @@ impl Spawner<'_, '_, '_, '_, '_> {
         pos: Coords,
         monster: MonsterType,
         rng: &mut fastrand::Rng,
-    ) {
+    ) -> Entity {
+        let def = self.monster_defs.get(monster);
         let mover = AiMover::new(pos, &mut self.map_data.monster_map);
-        let pos = mover.to_vec(monster.jumps(), 0.0);
-        let uv = monster.get_tile_seq(&self.render_res.sprites);
+        let pos = mover.to_vec(def.jumps, 0.0);
+        let uv = def.get_tile_seq(&self.render_res.sprites);
 
-        self.commands
-            .spawn(uv.to_sprite_bundle(pos, &mut self.meshes, &mut self.render_res))
+        let mut entity =
+            self.commands
+                .spawn(uv.to_sprite_bundle(pos, &mut self.meshes, &mut self.render_res));
+        entity
             .insert(crate::render::Animation::new(uv, rng.f32() * 0.04 + 0.16))
-            .insert(monster.make_ai())
+            .insert(def.make_ai())
             .insert(mover)
-            .insert(monster.make_stats())
-            .insert(monster.make_weapon())
+            .insert(def.make_stats(monster))
+            .insert(StatusEffects::default())
             .insert(crate::physics::Collider::new(pos, 0.5));
+
+        if let Some(weapon) = def.make_weapon(&self.weapon_defs) {
+            entity.insert(weapon);
+        }
+        if let Some(boss) = &def.boss {
+            entity.insert(Boss::new(boss));
+        }
+        entity.id()
+    }
+
+    /// Spawns one of the monsters that are only spawned as part of a boss fight.
+    fn spawn_named_monster(&mut self, pos: Coords, name: &str, rng: &mut fastrand::Rng) -> Entity {
+        let monster = self
+            .monster_defs
+            .find(name)
+            .unwrap_or_else(|| panic!("Monster `{}` is missing in monsters.ron", name));
+        self.spawn_monster_at_pos(pos, monster, rng)
     }
 
     pub fn choose_monster_pos(&mut self, rng: &mut fastrand::Rng) -> Result<Coords, &'static str> {
@@ -150,7 +192,7 @@ impl Spawner<'_, '_, '_, '_, '_> {
                     .insert(sprite);
             }
             SpawnObject::Monster { monster_type } => {
-                self.spawn_monster_at_pos(pos, *monster_type, rng)
+                self.spawn_monster_at_pos(pos, *monster_type, rng);
             }
             SpawnObject::Door {
                 door_type,
@@ -171,7 +213,9 @@ impl Spawner<'_, '_, '_, '_, '_> {
                 let transform =
                     Transform::from_translation(pos.to_vec(0.5)).looking_to(direction, Vec3::Y);
 
-                self.commands
+                let required_key = door.required_key;
+                let entity = self
+                    .commands
                     .spawn(PbrBundle {
                         mesh: self
                             .render_res
@@ -184,7 +228,13 @@ impl Spawner<'_, '_, '_, '_, '_> {
                     .insert(Interactable::SelfTrigger)
                     .insert(Collider::new(pos.to_vec(0.5), 0.5))
                     .insert(door.make_sprite3d())
-                    .insert(door);
+                    .insert(door)
+                    .id();
+
+                self.map_data.doors[pos] = Some(DoorRef {
+                    entity,
+                    required_key,
+                });
             }
             SpawnObject::Shop => {
                 let uv = &self.render_res.sprites.misc["vending_machine.png"];
@@ -202,7 +252,27 @@ impl Spawner<'_, '_, '_, '_, '_> {
                     .insert(FaceCamera)
                     .insert(sprite);
             }
-            SpawnObject::Phylactery {} => self.spawn_item_at_pos(pos, Pickup::Phylactery),
+            SpawnObject::Phylactery => {
+                let entity = self.spawn_named_monster(pos, "phylactery", rng);
+                self.commands.entity(entity).insert(Phylactery);
+            }
+            SpawnObject::Lich => {
+                self.spawn_named_monster(pos, "lich", rng);
+            }
+            SpawnObject::DartTrap { dir } => {
+                let weapon_type = self
+                    .weapon_defs
+                    .find("dart_trap")
+                    .expect("Weapon `dart_trap` is missing in weapons.ron");
+                let dir = Vec3::new(dir.x as f32, 0.0, dir.z as f32);
+
+                // Fire from just outside the wall, so the darts don't hit it right away
+                self.commands
+                    .spawn(Transform::from_translation(pos.to_vec(0.5) + dir * 0.6))
+                    .insert(crate::lifecycle::LevelObject)
+                    .insert(DartTrap { dir })
+                    .insert(self.weapon_defs.make_weapon(weapon_type));
+            }
         }
     }
 }
@@ -20,6 +20,11 @@ pub enum SpawnObject {
     },
     Shop,
     Phylactery,
+    Lich,
+    /// Placed in a wall, firing in `dir`.
+    DartTrap {
+        dir: Coords,
+    },
 }
 
 impl SpawnObject {
@@ -34,6 +39,7 @@ impl SpawnObject {
                 }
             }
             SpawnObject::Shop => true,
+            SpawnObject::DartTrap { dir } => grid[pos].is_solid() && !grid[pos + *dir].is_solid(),
             _ => true,
         }
     }
@@ -1,8 +1,12 @@
 use bevy::prelude::*;
 
-use crate::combat::{player::InputState, CreatureStats};
+use crate::combat::{
+    arsenal::Arsenal, boss::Boss, monsterdefs::MonsterDefs, player::InputState, weapon::WeaponType,
+    weapondefs::WeaponDefs, CreatureStats,
+};
 
 use super::styles::*;
+use crate::ghost::Ghost;
 
 #[derive(Component, PartialEq, Clone, Copy)]
 pub enum HudField {
@@ -12,6 +16,10 @@ pub enum HudField {
     Level(u8),
     Time(i32),
     Dir(f32),
+    /// The selected weapon and its ammo. Negative ammo means it doesn't use any.
+    Weapon(Option<WeaponType>, i16),
+    /// Tenths of a second behind the ghost at the start of the level
+    Ghost(Option<i32>),
     //    Status,
 }
 
@@ -20,7 +28,10 @@ impl HudField {
         &mut self,
         game: &crate::GameInfo,
         stats_query: &Query<&CreatureStats>,
+        arsenal_query: &Query<&Arsenal>,
+        weapon_defs: &WeaponDefs,
         input: &InputState,
+        ghost: &Ghost,
     ) {
         let Some(player) = game.player else {return;};
         match self {
@@ -34,6 +45,12 @@ impl HudField {
             HudField::Level(val) => *val = game.level,
             HudField::Time(val) => *val = game.time.elapsed_secs() as i32,
             HudField::Dir(val) => *val = input.yaw,
+            HudField::Weapon(weapon, ammo) => {
+                let Ok(arsenal) = arsenal_query.get(player) else {return;};
+                *weapon = Some(arsenal.current());
+                *ammo = arsenal.current_ammo(weapon_defs).unwrap_or(-1);
+            }
+            HudField::Ghost(val) => *val = ghost.delta.map(|delta| (delta * 10.0).round() as i32),
         };
     }
 
@@ -41,15 +58,18 @@ impl HudField {
         &mut self,
         game: &crate::GameInfo,
         stats_query: &Query<&CreatureStats>,
+        arsenal_query: &Query<&Arsenal>,
+        weapon_defs: &WeaponDefs,
         input: &InputState,
+        ghost: &Ghost,
     ) -> bool {
         let old = *self;
-        self.update_impl(game, stats_query, input);
+        self.update_impl(game, stats_query, arsenal_query, weapon_defs, input, ghost);
 
         *self == old
     }
 
-    fn make_text(&self) -> String {
+    fn make_text(&self, weapon_defs: &WeaponDefs) -> String {
         match self {
             HudField::Hp(val, max) => format!("HP: {}/{}", val, max),
             HudField::Score(val) => format!("Score: {}", val),
@@ -65,6 +85,17 @@ impl HudField {
                 ][index % 16];
                 format!("Dir: {}", str_val)
             }
+            HudField::Ghost(None) => String::new(),
+            HudField::Ghost(Some(val)) => format!("Ghost: {:+.1}s", *val as f32 / 10.0),
+            HudField::Weapon(None, _) => String::new(),
+            HudField::Weapon(Some(weapon), ammo) => {
+                let title = &weapon_defs.get(*weapon).title;
+                if *ammo < 0 {
+                    title.clone()
+                } else {
+                    format!("{}: {}", title, ammo)
+                }
+            }
         }
     }
 }
@@ -72,6 +103,16 @@ impl HudField {
 #[derive(Component)]
 pub struct Hud;
 
+/// The health bar of the boss, hidden while no boss is fighting the player.
+#[derive(Component)]
+pub struct BossBar;
+
+#[derive(Component)]
+pub struct BossBarFill;
+
+#[derive(Component)]
+pub struct BossBarText;
+
 pub fn spawn(mut commands: Commands, asset_server: Res<AssetServer>) {
     let _hud = commands
         .spawn((
@@ -117,30 +158,126 @@ pub fn spawn(mut commands: Commands, asset_server: Res<AssetServer>) {
                 make_text(&asset_server, "", FONT_P, TextAlignment::Center),
                 HudField::Dir(f32::NEG_INFINITY),
             ));
+            parent.spawn((
+                make_text(&asset_server, "", FONT_P, TextAlignment::Center),
+                HudField::Weapon(None, -1),
+            ));
+            parent.spawn((
+                make_text(&asset_server, "", FONT_P, TextAlignment::Center),
+                HudField::Ghost(Some(i32::MIN)),
+            ));
         })
         .id();
+
+    commands
+        .spawn((
+            BossBar,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(116.0),
+                    left: Val::Percent(25.0),
+                    width: Val::Percent(50.0),
+                    height: Val::Px(32.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgb(0.2, 0.0, 0.0).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                BossBarFill,
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(0.0),
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                },
+            ));
+            parent.spawn((
+                BossBarText,
+                make_text(&asset_server, "", FONT_P, TextAlignment::Center),
+            ));
+        });
 }
 
+#[allow(clippy::too_many_arguments)] // Not really applicable for bevy systems
 pub fn update_hud(
     mut query: Query<(&mut Text, &mut HudField)>,
     mut game: ResMut<crate::GameInfo>,
     stats_query: Query<&CreatureStats>,
+    arsenal_query: Query<&Arsenal>,
+    weapon_defs: Res<WeaponDefs>,
     input: Res<InputState>,
+    ghost: Res<Ghost>,
     time: Res<Time>,
 ) {
     game.time.tick(time.delta());
 
     for (mut text, mut updated) in &mut query {
-        if !updated.update(&game, &stats_query, &input) {
+        if !updated.update(
+            &game,
+            &stats_query,
+            &arsenal_query,
+            &weapon_defs,
+            &input,
+            &ghost,
+        ) {
             continue;
         }
 
-        text.sections[0].value = updated.make_text();
+        text.sections[0].value = updated.make_text(&weapon_defs);
     }
 }
 
-pub fn despawn(mut commands: Commands, query: Query<Entity, With<Hud>>) {
-    if let Ok(entity) = query.get_single() {
+pub fn update_boss_bar(
+    boss_query: Query<(&CreatureStats, &Boss)>,
+    monster_defs: Res<MonsterDefs>,
+    mut bar_query: Query<&mut Visibility, With<BossBar>>,
+    mut fill_query: Query<&mut Style, With<BossBarFill>>,
+    mut text_query: Query<&mut Text, With<BossBarText>>,
+) {
+    let Ok(mut visibility) = bar_query.get_single_mut() else {return;};
+    let Some((stats, boss)) = boss_query.iter().next() else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Inherited;
+
+    if let Ok(mut style) = fill_query.get_single_mut() {
+        style.width = Val::Percent(stats.hp.max(0) as f32 * 100.0 / stats.hp_max as f32);
+    }
+
+    if let Ok(mut text) = text_query.get_single_mut() {
+        let name = stats
+            .monster_type
+            .map_or("Boss", |monster_type| &monster_defs.get(monster_type).name);
+        let value = if boss.shielded {
+            format!("{} (shielded)", name.to_uppercase())
+        } else {
+            name.to_uppercase()
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+pub fn despawn(
+    mut commands: Commands,
+    query: Query<Entity, With<Hud>>,
+    boss_bar_query: Query<Entity, With<BossBar>>,
+) {
+    for entity in query.iter().chain(boss_bar_query.iter()) {
         commands.entity(entity).despawn_recursive();
     }
 }
@@ -1,4 +1,5 @@
 use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
 
 use super::styles::*;
 use crate::{combat::CreatureStats, game::GameState, mapgen::style::LevelStyle, GameSettings};
@@ -46,7 +47,7 @@ pub struct Button {
     action: OnClick,
 }
 
-#[derive(Event, Clone, Copy)]
+#[derive(Event, Clone, Copy, Serialize, Deserialize)]
 pub enum OnClick {
     Play,
     PlayDaily,
@@ -30,7 +30,10 @@ impl Plugin for UIPlugin {
             )
             .add_systems(
                 Update,
-                (hud::update_hud.run_if(not(in_state(GameState::MainMenu))),),
+                (
+                    hud::update_hud.run_if(not(in_state(GameState::MainMenu))),
+                    hud::update_boss_bar.run_if(in_state(GameState::InGame)),
+                ),
             );
     }
 }